@@ -0,0 +1,153 @@
+//! The pieces every path is made of: intersecting a sphere, scattering off each material
+//! and generating camera rays with each lens. Run with `cargo bench --bench kernels`,
+//! criterion compares against the previous run and flags regressions.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use glam::{Vec2, Vec3};
+use std::hint::black_box;
+use std::sync::Arc;
+use tracer::camera::{Camera, Fisheye, Orthographic};
+use tracer::hit::{Hit, Hittable};
+use tracer::material::Material;
+use tracer::ray::Ray;
+use tracer::sphere::Sphere;
+use tracer::trace::RANGE;
+
+/// How many inputs every iteration goes through, so the per-item cost isn't lost in the
+/// timing overhead.
+const BATCH: usize = 1024;
+
+/// Rays from around a unit sphere at the origin, towards points near it so that about
+/// half of them hit.
+fn rays_at_sphere() -> Vec<Ray> {
+    (0..BATCH)
+        .map(|_| {
+            let origin = 4.0 * random_direction();
+            let target = 1.5 * random_direction();
+            Ray::new(origin, (target - origin).normalize())
+        })
+        .collect()
+}
+
+fn random_direction() -> Vec3 {
+    let z = 2.0 * rand::random::<f32>() - 1.0;
+    let phi = 2.0 * std::f32::consts::PI * rand::random::<f32>();
+    let r = (1.0 - z * z).sqrt();
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+fn sphere_hit(c: &mut Criterion) {
+    let lambert = Material::Lambert {
+        albedo: Vec3::splat(0.5),
+    };
+    let rays = rays_at_sphere();
+    let mut group = c.benchmark_group("sphere_hit");
+    group.throughput(Throughput::Elements(BATCH as u64));
+
+    let sphere = Sphere::new(Vec3::zero(), 1.0, lambert);
+    group.bench_function("static", |b| {
+        b.iter(|| {
+            rays.iter()
+                .filter(|r| sphere.hit(r, RANGE).is_some())
+                .count()
+        })
+    });
+    let mut moving = Sphere::new(Vec3::zero(), 1.0, lambert);
+    moving.motion = Vec3::new(0.2, 0.0, 0.0);
+    group.bench_function("moving", |b| {
+        b.iter(|| {
+            rays.iter()
+                .filter(|r| moving.hit(r, RANGE).is_some())
+                .count()
+        })
+    });
+    group.finish();
+}
+
+fn scatter(c: &mut Criterion) {
+    let materials = [
+        (
+            "lambert",
+            Material::Lambert {
+                albedo: Vec3::splat(0.5),
+            },
+        ),
+        (
+            "metal",
+            Material::Metal {
+                albedo: Vec3::splat(0.8),
+                fuzz: 0.3,
+            },
+        ),
+        (
+            "dielectric",
+            Material::Dielectric {
+                albedo: Vec3::one(),
+                ref_idx: 1.5,
+            },
+        ),
+    ];
+    let mut group = c.benchmark_group("scatter");
+
+    for &(name, material) in &materials {
+        let sphere = Sphere::new(Vec3::zero(), 1.0, material);
+        let hits: Vec<(Ray, Hit)> = rays_at_sphere()
+            .into_iter()
+            .filter_map(|r| sphere.hit(&r, RANGE).map(|hit| (r, hit)))
+            .collect();
+        group.throughput(Throughput::Elements(hits.len() as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for (r, hit) in &hits {
+                    black_box(hit.mat.scatter(r, hit));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn get_ray(c: &mut Criterion) {
+    let camera = |aperture| {
+        Camera::new(
+            Vec3::new(13.0, 2.0, 3.0),
+            Vec3::zero(),
+            Vec3::unit_y(),
+            20.0,
+            16.0 / 9.0,
+            aperture,
+            10.0,
+        )
+    };
+    let cameras = [
+        ("pinhole", camera(0.0)),
+        ("thin_lens", camera(0.1)),
+        (
+            "orthographic",
+            camera(0.0).with_model(Arc::new(Orthographic { height: 4.0 })),
+        ),
+        (
+            "fisheye",
+            camera(0.0).with_model(Arc::new(Fisheye { fov: 180.0 })),
+        ),
+    ];
+    let uvs: Vec<Vec2> = (0..BATCH)
+        .map(|_| Vec2::new(rand::random(), rand::random()))
+        .collect();
+    let mut group = c.benchmark_group("get_ray");
+    group.throughput(Throughput::Elements(BATCH as u64));
+
+    for (name, camera) in &cameras {
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                for &uv in &uvs {
+                    black_box(camera.get_ray(uv));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(kernels, sphere_hit, scatter, get_ray);
+criterion_main!(kernels);
@@ -0,0 +1,105 @@
+//! Camera rays against a field of small spheres, first tested one sphere at a time as
+//! `impl Hittable for Vec<Sphere>` does, then through `SphereBvh` one ray at a time and
+//! in packets. Run with `cargo bench --bench packet`, and add `--features scalar` to
+//! see what SIMD adds.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use glam::Vec3;
+use tracer::bvh::SphereBvh;
+use tracer::camera::Camera;
+use tracer::hit::Hittable;
+use tracer::material::Material;
+use tracer::ray::Ray;
+use tracer::simd::LANES;
+use tracer::sphere::Sphere;
+
+const RANGE: [f32; 2] = [1e-3, f32::MAX];
+
+/// Small spheres on a grid over a large ground sphere, with a few big ones in the middle.
+fn spheres() -> Vec<Sphere> {
+    let lambert = Material::Lambert {
+        albedo: Vec3::splat(0.5),
+    };
+    let mut spheres = vec![Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, lambert)];
+    for a in -11..11 {
+        for b in -11..11 {
+            let center = Vec3::new(
+                a as f32 + 0.9 * rand::random::<f32>(),
+                0.2,
+                b as f32 + 0.9 * rand::random::<f32>(),
+            );
+            spheres.push(Sphere::new(center, 0.2, lambert));
+        }
+    }
+    for &x in &[-4.0, 0.0, 4.0] {
+        spheres.push(Sphere::new(Vec3::new(x, 1.0, 0.0), 1.0, lambert));
+    }
+    spheres
+}
+
+/// Four jittered samples per pixel, the way `trace::render` makes its packets.
+fn camera_rays(dimensions: (usize, usize)) -> Vec<Ray> {
+    let (width, height) = dimensions;
+    let camera = Camera::new(
+        Vec3::new(13.0, 2.0, 3.0),
+        Vec3::zero(),
+        Vec3::unit_y(),
+        20.0,
+        width as f32 / height as f32,
+        0.1,
+        10.0,
+    );
+    let mut rays = Vec::with_capacity(width * height * LANES);
+    for y in 0..height {
+        for x in 0..width {
+            for _ in 0..LANES {
+                let uv = glam::Vec2::new(
+                    (x as f32 + rand::random::<f32>()) / width as f32,
+                    (y as f32 + rand::random::<f32>()) / height as f32,
+                );
+                rays.push(camera.get_ray(uv));
+            }
+        }
+    }
+    rays
+}
+
+/// How many of the rays hit something, one at a time.
+fn hits(world: &dyn Hittable, rays: &[Ray]) -> usize {
+    rays.iter()
+        .filter(|r| world.hit(r, RANGE).is_some())
+        .count()
+}
+
+/// How many of the rays hit something, a packet at a time.
+fn packet_hits(bvh: &SphereBvh, rays: &[Ray]) -> usize {
+    rays.chunks(LANES)
+        .map(|packet| {
+            let hits = bvh.hit_packet(packet, RANGE);
+            hits.iter().filter(|hit| hit.is_some()).count()
+        })
+        .sum()
+}
+
+fn packet(c: &mut Criterion) {
+    let spheres = spheres();
+    let rays = camera_rays((320, 180));
+    println!("{} spheres, {} camera rays", spheres.len(), rays.len());
+
+    let list = spheres.clone();
+    let bvh = SphereBvh::new(spheres);
+    let list_hits = hits(&list, &rays);
+    assert_eq!(hits(&bvh, &rays), list_hits);
+    assert_eq!(packet_hits(&bvh, &rays), list_hits);
+
+    let mut group = c.benchmark_group("packet");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(rays.len() as u64));
+    group.bench_function("Vec<Sphere>", |b| b.iter(|| hits(&list, &rays)));
+    group.bench_function("bvh", |b| b.iter(|| hits(&bvh, &rays)));
+    group.bench_function("bvh packets", |b| b.iter(|| packet_hits(&bvh, &rays)));
+    group.finish();
+}
+
+criterion_group!(benches, packet);
+criterion_main!(benches);
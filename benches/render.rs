@@ -0,0 +1,55 @@
+//! Whole renders of the built-in scenes at a small size, reported in rays per second. Run
+//! with `cargo bench --bench render`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use glam::Vec3;
+use tracer::camera::Camera;
+use tracer::scene::{self, Scene};
+use tracer::trace::{self, RenderSettings};
+
+fn render(c: &mut Criterion) {
+    let settings = RenderSettings {
+        dimensions: (64, 36),
+        samples: 4,
+        depth: 16,
+        roulette: Some(3),
+        seed: None,
+        aovs: Vec::new(),
+    };
+    let camera = Camera::new(
+        Vec3::new(0.0, 0.5, 2.0),
+        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::unit_y(),
+        60.0,
+        16.0 / 9.0,
+        0.0,
+        3.0,
+    );
+    let scenes = [
+        ("default", Scene::new(scene::default_world())),
+        ("lights", scene::lights_scene()),
+        ("cloud", Scene::new(scene::cloud_world())),
+    ];
+
+    let mut group = c.benchmark_group("render");
+    group.sample_size(10);
+    for (name, scene) in &scenes {
+        // paths end at random, so the rays of one render stand for all of them
+        let rays = trace::render(&camera, scene, &settings).rays;
+        println!(
+            "{}: {} primary, {} secondary rays, {} bvh nodes visited",
+            name,
+            rays.camera,
+            rays.secondary(),
+            rays.node_visits
+        );
+        group.throughput(Throughput::Elements(rays.total()));
+        group.bench_function(*name, |b| {
+            b.iter(|| trace::render(&camera, scene, &settings))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
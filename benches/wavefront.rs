@@ -0,0 +1,56 @@
+//! The recursive integrator against the wavefront one, with and without sorting its
+//! queues, on the default spheres lit by the lights. Both trace every ray through the
+//! same `SphereBvh`, so only the integrators differ. Run with
+//! `cargo bench --bench wavefront`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use glam::Vec3;
+use tracer::camera::Camera;
+use tracer::frame::Frame;
+use tracer::scene;
+use tracer::trace::{self, RenderSettings};
+use tracer::wavefront::Wavefront;
+
+fn integrators(c: &mut Criterion) {
+    let dimensions = (320, 180);
+    let camera = Camera::new(
+        Vec3::new(0.0, 0.5, 2.0),
+        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::unit_y(),
+        60.0,
+        dimensions.0 as f32 / dimensions.1 as f32,
+        0.0,
+        3.0,
+    );
+    let scene = scene::lights_scene();
+    let settings = RenderSettings {
+        dimensions,
+        samples: 16,
+        depth: 50,
+        roulette: Some(3),
+        seed: None,
+        aovs: Vec::new(),
+    };
+
+    let integrators: [(&str, &dyn Fn() -> Frame); 3] = [
+        ("recursive", &|| trace::render(&camera, &scene, &settings)),
+        ("wavefront", &|| {
+            Wavefront::default().render(&camera, &scene, &settings)
+        }),
+        ("wavefront unsorted", &|| {
+            Wavefront { sort: false }.render(&camera, &scene, &settings)
+        }),
+    ];
+
+    let mut group = c.benchmark_group("wavefront");
+    group.sample_size(10);
+    for (name, render) in &integrators {
+        // paths end at random, so the rays of one render stand for all of them
+        group.throughput(Throughput::Elements(render().rays.total()));
+        group.bench_function(*name, |b| b.iter(render));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, integrators);
+criterion_main!(benches);
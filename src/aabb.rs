@@ -0,0 +1,43 @@
+use crate::ray::Ray;
+use glam::f32::Vec3;
+
+#[derive(Copy, Clone)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Smallest box containing both.
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    /// Slab test, returns the parametric interval of `r` inside the box clipped to `range`.
+    pub fn hit(&self, r: &Ray, range: [f32; 2]) -> Option<[f32; 2]> {
+        let inv_dir = r.dir.reciprocal();
+        let t0 = (self.min - r.origin) * inv_dir;
+        let t1 = (self.max - r.origin) * inv_dir;
+
+        let t_enter = t0.min(t1).max_element().max(range[0]);
+        let t_exit = t0.max(t1).min_element().min(range[1]);
+
+        if t_enter < t_exit {
+            Some([t_enter, t_exit])
+        } else {
+            None
+        }
+    }
+}
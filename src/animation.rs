@@ -0,0 +1,226 @@
+use crate::camera::Camera;
+use crate::material::Material;
+use crate::scene::Scene;
+use glam::Vec3;
+
+/// How a track gets from one key to the next.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    /// Cubic Bézier with its handles a third of the way to the neighbouring keys, along the
+    /// Catmull-Rom tangent through the key. The first and last keys are flat, so motion
+    /// eases in and out of them.
+    Bezier,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Key {
+    pub time: f32,
+    /// Vector parameters use every component, numbers only x.
+    pub value: Vec3,
+    /// From this key to the next one.
+    pub interpolation: Interpolation,
+}
+
+/// Scene parameters that can change over time. Objects are indices into the world and
+/// have to be spheres, lights are indices into the scene lights.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Parameter {
+    Center(usize),
+    Radius(usize),
+    Albedo(usize),
+    Fuzz(usize),
+    RefIdx(usize),
+    Intensity(usize),
+    /// Field of view of the camera, in degrees.
+    Fov,
+    /// Diameter of the camera lens.
+    Aperture,
+}
+
+impl Parameter {
+    /// Index of the world object this animates, if it animates one.
+    pub fn object(self) -> Option<usize> {
+        match self {
+            Parameter::Center(object)
+            | Parameter::Radius(object)
+            | Parameter::Albedo(object)
+            | Parameter::Fuzz(object)
+            | Parameter::RefIdx(object) => Some(object),
+            Parameter::Intensity(_) | Parameter::Fov | Parameter::Aperture => None,
+        }
+    }
+
+    /// The same parameter of another world object.
+    fn with_object(self, object: usize) -> Parameter {
+        match self {
+            Parameter::Center(_) => Parameter::Center(object),
+            Parameter::Radius(_) => Parameter::Radius(object),
+            Parameter::Albedo(_) => Parameter::Albedo(object),
+            Parameter::Fuzz(_) => Parameter::Fuzz(object),
+            Parameter::RefIdx(_) => Parameter::RefIdx(object),
+            other => other,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Track {
+    pub parameter: Parameter,
+    /// Sorted by time.
+    pub keys: Vec<Key>,
+}
+
+impl Track {
+    /// Value at `time`, held before the first key and after the last.
+    pub fn value_at(&self, time: f32) -> Option<Vec3> {
+        let keys = &self.keys;
+        let last = keys.len().checked_sub(1)?;
+        if time <= keys[0].time {
+            return Some(keys[0].value);
+        }
+        if time >= keys[last].time {
+            return Some(keys[last].value);
+        }
+
+        // only NaN times get past the checks above without a key at or before `time`
+        let index = match keys[..last].iter().rposition(|key| key.time <= time) {
+            Some(index) => index,
+            None => return Some(keys[0].value),
+        };
+        let (a, b) = (keys[index], keys[index + 1]);
+        let span = b.time - a.time;
+        let s = (time - a.time) / span;
+
+        Some(match a.interpolation {
+            Interpolation::Linear => a.value + (b.value - a.value) * s,
+            Interpolation::Bezier => {
+                let handle_a = a.value + self.slope(index) * span / 3.0;
+                let handle_b = b.value - self.slope(index + 1) * span / 3.0;
+                let t = 1.0 - s;
+                a.value * (t * t * t)
+                    + handle_a * (3.0 * t * t * s)
+                    + handle_b * (3.0 * t * s * s)
+                    + b.value * (s * s * s)
+            }
+        })
+    }
+
+    /// Rate of change through a key, zero at the ends.
+    fn slope(&self, index: usize) -> Vec3 {
+        if index == 0 || index + 1 == self.keys.len() {
+            return Vec3::zero();
+        }
+        let (previous, next) = (self.keys[index - 1], self.keys[index + 1]);
+        (next.value - previous.value) / (next.time - previous.time)
+    }
+}
+
+/// Every animated parameter of a scene and its camera.
+#[derive(Clone, Default, Debug)]
+pub struct Animation {
+    pub tracks: Vec<Track>,
+}
+
+impl Animation {
+    /// Adds a key to the track of `parameter`, replacing the key it had at the same time.
+    pub fn insert(&mut self, parameter: Parameter, key: Key) {
+        let track = match self.tracks.iter().position(|t| t.parameter == parameter) {
+            Some(index) => &mut self.tracks[index],
+            None => {
+                self.tracks.push(Track {
+                    parameter,
+                    keys: Vec::new(),
+                });
+                self.tracks.last_mut().unwrap()
+            }
+        };
+
+        match track.keys.iter().position(|k| k.time >= key.time) {
+            Some(index) if track.keys[index].time == key.time => track.keys[index] = key,
+            Some(index) => track.keys.insert(index, key),
+            None => track.keys.push(key),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    /// Drops the tracks of the world object at `index` and renumbers the ones after it,
+    /// to follow the object being removed from the world.
+    pub fn remove_object(&mut self, index: usize) {
+        self.tracks
+            .retain(|track| track.parameter.object() != Some(index));
+        for track in &mut self.tracks {
+            match track.parameter.object() {
+                Some(object) if object > index => {
+                    track.parameter = track.parameter.with_object(object - 1)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Time of the last key.
+    pub fn duration(&self) -> f32 {
+        self.tracks
+            .iter()
+            .filter_map(|track| track.keys.last())
+            .map(|key| key.time)
+            .fold(0.0, f32::max)
+    }
+
+    /// Sets every animated parameter to its value at `time`. Spheres also move to where
+    /// they are `shutter` later over the ray times, which blurs them.
+    pub fn apply(&self, scene: &mut Scene, camera: &mut Camera, time: f32, shutter: f32) {
+        for track in &self.tracks {
+            let value = match track.value_at(time) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            match track.parameter {
+                Parameter::Center(object) => {
+                    if let Some(sphere) = scene.sphere_mut(object) {
+                        sphere.center = value;
+                        sphere.motion = track.value_at(time + shutter).unwrap() - value;
+                    }
+                }
+                Parameter::Radius(object) => {
+                    if let Some(sphere) = scene.sphere_mut(object) {
+                        sphere.radius = value.x();
+                    }
+                }
+                Parameter::Albedo(object) => {
+                    if let Some(sphere) = scene.sphere_mut(object) {
+                        sphere.mat = sphere.mat.with_albedo(value);
+                    }
+                }
+                Parameter::Fuzz(object) => {
+                    if let Some(sphere) = scene.sphere_mut(object) {
+                        if let Material::Metal { albedo, .. } = sphere.mat {
+                            let fuzz = value.x();
+                            sphere.mat = Material::Metal { albedo, fuzz };
+                        }
+                    }
+                }
+                Parameter::RefIdx(object) => {
+                    if let Some(sphere) = scene.sphere_mut(object) {
+                        if let Material::Dielectric { albedo, .. } = sphere.mat {
+                            let ref_idx = value.x();
+                            sphere.mat = Material::Dielectric { albedo, ref_idx };
+                        }
+                    }
+                }
+                Parameter::Intensity(light) => {
+                    if let Some(light) = scene.lights.get_mut(light) {
+                        *light = light.with_intensity(value);
+                    }
+                }
+                Parameter::Fov => camera.set_fov(value.x()),
+                Parameter::Aperture => camera.lens_radius = value.x() / 2.0,
+            }
+        }
+    }
+}
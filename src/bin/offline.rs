@@ -1,30 +1,215 @@
 use glam::Vec3;
 use std::ops::Div;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tracer::camera::Camera;
-use tracer::trace::image;
+use tracer::camera_path::CameraPath;
+use tracer::denoise::Denoiser;
+use tracer::environment::EnvironmentMap;
+use tracer::frame::Aov;
+use tracer::scene::{self, Scene};
+use tracer::scene_file;
+use tracer::sky::Sky;
+use tracer::trace::{self, RayCount, RenderSettings};
+use tracer::wavefront::Wavefront;
+
+const USAGE: &str = "\
+usage: offline [options] [default | cloud | lights | scene file]
+    --resolution W H, --samples N, --roulette N, --seed N, --wavefront
+    --environment PATH, --environment-rotation DEGREES, --sky ELEVATION AZIMUTH TURBIDITY
+    --aovs NAME,..., --denoise
+    --camera-path PATH, --fps N, --frames FIRST LAST, --shutter FRACTION, --output DIR";
 
 fn main() {
     let instant_before_tracing = Instant::now();
 
-    let dimensions = (1280, 720);
-    let samples = 1000;
+    let mut dimensions = (1280, 720);
+    let mut samples = 1000;
     let depth = 100;
+    // bounces before paths may end early, anything but a number keeps them all
+    let mut roulette = Some(3);
+    let mut seed = None;
 
-    let camera = &Camera::new(
-        Vec3::new(0.0, 0.25, 0.0),
-        Vec3::new(0.0, 0.0, -1.0),
-        Vec3::new(0.0, 1.0, 0.0),
-        100.0,
-        dimensions.0 as f32 / dimensions.1 as f32,
-        0.025,
-        1.0,
-    );
+    let mut world_name = String::from("default");
+    let mut environment_path = None;
+    let mut environment_rotation = 0.0;
+    let mut sky = None;
+    let mut aovs = Vec::new();
+    let mut denoise = false;
+    let mut camera_path = None;
+    let mut fps = 24.0;
+    let mut output = String::from("frames");
+    let mut frame_range = None;
+    // fraction of a frame the shutter stays open for
+    let mut shutter = 0.5;
+    let mut wavefront = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--environment" => environment_path = args.next(),
+            "--environment-rotation" => {
+                environment_rotation = args.next().and_then(|a| a.parse().ok()).unwrap_or(0.0)
+            }
+            "--aovs" => {
+                for name in args.next().unwrap_or_default().split(',') {
+                    match Aov::from_name(name) {
+                        Some(aov) => aovs.push(aov),
+                        None => eprintln!("unknown AOV {}", name),
+                    }
+                }
+            }
+            "--denoise" => denoise = true,
+            "--wavefront" => wavefront = true,
+            "--roulette" => roulette = args.next().and_then(|a| a.parse().ok()),
+            "--seed" => seed = args.next().and_then(|a| a.parse().ok()),
+            "--camera-path" => camera_path = args.next(),
+            "--fps" => fps = args.next().and_then(|a| a.parse().ok()).unwrap_or(fps),
+            "--samples" => samples = args.next().and_then(|a| a.parse().ok()).unwrap_or(samples),
+            "--output" => output = args.next().unwrap_or(output),
+            "--frames" => {
+                let mut param = || args.next().and_then(|a| a.parse::<u32>().ok()).unwrap_or(0);
+                frame_range = Some((param(), param()));
+            }
+            "--shutter" => shutter = args.next().and_then(|a| a.parse().ok()).unwrap_or(shutter),
+            "--resolution" => {
+                let mut param =
+                    |default| args.next().and_then(|a| a.parse().ok()).unwrap_or(default);
+                dimensions = (param(dimensions.0), param(dimensions.1));
+            }
+            "--sky" => {
+                let mut param =
+                    |default| args.next().and_then(|a| a.parse().ok()).unwrap_or(default);
+                sky = Some(Sky::new(param(45.0), param(0.0), param(3.0)));
+            }
+            "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if arg.starts_with("--") => {
+                eprintln!("unknown option {}\n{}", arg, USAGE);
+                std::process::exit(2);
+            }
+            _ => world_name = arg,
+        }
+    }
+
+    let default_camera = || {
+        Camera::new(
+            Vec3::new(0.0, 0.25, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            100.0,
+            1.0,
+            0.025,
+            1.0,
+        )
+    };
+
+    let (mut scene, mut camera) = match world_name.as_str() {
+        "default" => (Scene::new(scene::default_world()), default_camera()),
+        "cloud" => (Scene::new(scene::cloud_world()), default_camera()),
+        "lights" => (scene::lights_scene(), default_camera()),
+        path => scene_file::load(path).unwrap_or_else(|error| fail(path, error)),
+    };
+    camera.aspect = dimensions.0 as f32 / dimensions.1 as f32;
+
+    if let Some(sky) = sky {
+        scene.environment = Box::new(sky);
+    }
+    if let Some(path) = environment_path {
+        let map = EnvironmentMap::load(&path, environment_rotation);
+        scene.environment = Box::new(map.unwrap_or_else(|error| fail(&path, error)));
+    }
+
+    // the denoiser needs its guides even when they aren't written out
+    let written_aovs = aovs.clone();
+    if denoise {
+        for &aov in &[Aov::Albedo, Aov::Normal, Aov::Depth] {
+            if !aovs.contains(&aov) {
+                aovs.push(aov);
+            }
+        }
+    }
+
+    let settings = RenderSettings {
+        dimensions,
+        samples,
+        depth,
+        roulette,
+        seed,
+        aovs,
+    };
+    let render = |camera: &Camera, scene: &Scene, color_path: &str, exr_path: &str| {
+        let instant_before_render = Instant::now();
+        let mut frame = if wavefront {
+            Wavefront::default().render(camera, scene, &settings)
+        } else {
+            trace::render(camera, scene, &settings)
+        };
+        let render_time = instant_before_render.elapsed();
+        if denoise {
+            frame.color = Denoiser::default().denoise(&frame);
+        }
+        frame.aovs.retain(|(aov, _)| written_aovs.contains(aov));
 
-    let mut buffer = image(camera, dimensions, samples, depth);
+        let mut buffer = trace::to_rgba8(&frame.color);
+        trace::write_to_tga(color_path, dimensions, &mut buffer).unwrap();
+        if !frame.aovs.is_empty() {
+            frame.write_exr(exr_path).unwrap();
+        }
+        (frame.rays, render_time)
+    };
+
+    // a sequence of frames for animations, otherwise a single image
+    let camera_path =
+        camera_path.map(|path| CameraPath::load(&path).unwrap_or_else(|error| fail(&path, error)));
+    let duration = camera_path
+        .as_ref()
+        .map_or(0.0, CameraPath::duration)
+        .max(scene.animation.duration());
+    if camera_path.is_some() || !scene.animation.is_empty() {
+        frame_range = frame_range.or(Some((0, (duration * fps).floor() as u32)));
+    }
+
+    let mut frames = 1;
+    // rays and the time spent tracing them, leaving out denoising and writing files
+    let mut rays = RayCount::default();
+    let mut render_time = Duration::default();
+    match frame_range {
+        Some((first, last)) => {
+            std::fs::create_dir_all(&output).unwrap();
+            frames = last.saturating_sub(first) + 1;
+            for index in first..=last {
+                let time = index as f32 / fps;
+                scene.set_time(&mut camera, time, shutter / fps);
+                if let Some(path) = &camera_path {
+                    if let Some((origin, rotation)) = path.pose_at(time) {
+                        camera.set_pose(origin, rotation);
+                    }
+                    if camera.refocus {
+                        camera.autofocus(&scene.world);
+                    }
+                }
+                let (frame_rays, frame_time) = render(
+                    &camera,
+                    &scene,
+                    &format!("{}/frame_{:04}.tga", output, index),
+                    &format!("{}/frame_{:04}.exr", output, index),
+                );
+                rays = rays + frame_rays;
+                render_time += frame_time;
+                println!("frame {}/{}", index - first + 1, frames);
+            }
+        }
+        None => {
+            let (frame_rays, frame_time) = render(&camera, &scene, "output.tga", "output.exr");
+            rays = frame_rays;
+            render_time = frame_time;
+        }
+    }
 
     let time_elapsed_tracing = instant_before_tracing.elapsed();
-    let time_per_pixel = time_elapsed_tracing.div((dimensions.0 * dimensions.1) as u32);
+    let time_per_pixel = time_elapsed_tracing.div((dimensions.0 * dimensions.1) as u32 * frames);
     let time_per_sample = time_per_pixel.div(samples as u32);
 
     println!(
@@ -36,6 +221,20 @@ fn main() {
         time_per_pixel.as_nanos() as f32 * 1e-3
     );
     println!("{:>6} nanos/sample", time_per_sample.as_nanos() as f32);
+    println!(
+        "{:>6.2} Mrays/s",
+        rays.total() as f64 / render_time.as_secs_f64().max(1e-6) * 1e-6
+    );
+    println!(
+        "{:>6.2} M primary and {:.2} M secondary rays, {:.2} M bvh nodes visited",
+        rays.camera as f64 * 1e-6,
+        rays.secondary() as f64 * 1e-6,
+        rays.node_visits as f64 * 1e-6
+    );
+}
 
-    tracer::trace::write_to_tga("output.tga", dimensions, &mut buffer).unwrap();
+/// Reports a file that couldn't be loaded and gives up.
+fn fail(path: &str, error: std::io::Error) -> ! {
+    eprintln!("couldn't load {}: {}", path, error);
+    std::process::exit(1)
 }
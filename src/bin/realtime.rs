@@ -1,11 +1,11 @@
 #![allow(unused_imports)]
-#![feature(clamp)]
 
 use std::time::Instant;
 
 use glium::backend::glutin::glutin::{
     dpi::LogicalSize, ContextBuilder, DeviceEvent, ElementState, Event, EventsLoop, GlProfile,
-    GlRequest, KeyboardInput, MouseCursor, Robustness, VirtualKeyCode, Window, WindowEvent,
+    GlRequest, KeyboardInput, MouseButton, MouseCursor, MouseScrollDelta, Robustness,
+    VirtualKeyCode, Window, WindowEvent,
 };
 use glium::glutin::WindowBuilder;
 use glium::texture::{
@@ -22,8 +22,25 @@ use glium::texture::{MipmapsOption, SrgbFormat};
 use glium::uniforms::{EmptyUniforms, MagnifySamplerFilter};
 use glium::RawUniformValue::Vec2;
 use image::math::utils::clamp;
+use std::collections::{HashMap, VecDeque};
 use std::convert::identity;
+use std::fs;
+use std::io;
 use tracer::camera::Camera;
+use tracer::camera_path::{CameraPath, Keyframe};
+use tracer::gpu::GpuTracer;
+use tracer::hit::Hittable;
+use tracer::material::Material;
+use tracer::overlay::{Overlay, CHAR_WIDTH, LINE_HEIGHT};
+use tracer::scene::{self, Scene};
+use tracer::scene_file;
+use tracer::sphere::Sphere;
+use tracer::viewer::{self, Backend, CameraController, Controller, Viewer};
+
+const USAGE: &str = "\
+usage: realtime [options] [scene file]
+    --bindings PATH, --backend cpu|gpu
+    --headless CAMERA_PATH, --fps N, --output DIR, --record PATH";
 
 #[derive(Clone, Copy)]
 struct Vertex {
@@ -32,18 +49,77 @@ struct Vertex {
 }
 
 fn main() {
-    let display_size = LogicalSize::new(1280.0, 720.0);
-    let resolution_scale = 0.325;
-    let resolution: (u32, u32) = (
-        (display_size.width * resolution_scale).round() as _,
-        (display_size.height * resolution_scale).round() as _,
-    );
+    let mut display_size = LogicalSize::new(1280.0, 720.0);
+    let mut resolution_scale = 0.325;
+    let mut progressive = false;
+
+    let mut scene_path = None;
+    let mut bindings = Bindings::default();
+    let mut headless = None;
+    let mut output = String::from("frames");
+    let mut record_path = String::from("camera.path");
+    let mut fps = 30.0;
+    let mut gpu = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bindings" => {
+                let path = args.next().unwrap_or_else(|| {
+                    eprintln!("--bindings needs a file\n{}", USAGE);
+                    std::process::exit(2)
+                });
+                bindings = Bindings::load(&path).unwrap_or_else(|error| fail(&path, error));
+            }
+            "--headless" => headless = args.next(),
+            "--output" => output = args.next().unwrap_or(output),
+            "--record" => record_path = args.next().unwrap_or(record_path),
+            "--fps" => fps = args.next().and_then(|a| a.parse().ok()).unwrap_or(fps),
+            "--backend" => gpu = args.next().as_deref() == Some("gpu"),
+            "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if arg.starts_with("--") => {
+                eprintln!("unknown option {}\n{}", arg, USAGE);
+                std::process::exit(2);
+            }
+            _ => scene_path = Some(arg),
+        }
+    }
+
+    // a scene file sets the world, the starting view and the camera model
+    let (mut scene, scene_camera) = match &scene_path {
+        Some(path) => scene_file::load(path).unwrap_or_else(|error| fail(path, error)),
+        None => (Scene::new(scene::default_world()), default_camera()),
+    };
+
+    if let Some(path) = headless {
+        let camera_path = CameraPath::load(&path).unwrap_or_else(|error| fail(&path, error));
+        let resolution = (
+            (display_size.width * resolution_scale).round() as usize,
+            (display_size.height * resolution_scale).round() as usize,
+        );
+        let replay = run_headless(
+            &scene,
+            &scene_camera,
+            &camera_path,
+            resolution,
+            fps,
+            &output,
+        );
+        if let Err(error) = replay {
+            eprintln!("couldn't write frames to {}: {}", output, error);
+            std::process::exit(1);
+        }
+        return;
+    }
 
     let (display, mut events_loop) = create_window(display_size);
     let gl_window = display.gl_window();
     let window: &Window = gl_window.window();
-    window.grab_cursor(true).unwrap();
-    window.hide_cursor(true);
+    let mut grabbed = true;
+    window.grab_cursor(grabbed).unwrap();
+    window.hide_cursor(grabbed);
     window.set_cursor_position(LogicalPosition::new(
         display_size.width * 0.5,
         display_size.height * 0.5,
@@ -52,24 +128,7 @@ fn main() {
     let geometry = create_fullscreen_geometry(&display);
 
     let composite_program = compile_composite_shader(&display);
-
-    let composite = SrgbTexture2d::empty_with_format(
-        &display,
-        SrgbFormat::U8U8U8U8,
-        MipmapsOption::NoMipmap,
-        resolution.0,
-        resolution.1,
-    )
-    .unwrap();
-
-    let accumulation = SrgbTexture2d::empty_with_format(
-        &display,
-        SrgbFormat::U8U8U8U8,
-        MipmapsOption::NoMipmap,
-        resolution.0,
-        resolution.1,
-    )
-    .unwrap();
+    let overlay_program = compile_overlay_shader(&display);
 
     let instant_start = Instant::now();
     let mut instant_last_frame = Instant::now();
@@ -77,18 +136,68 @@ fn main() {
     let mut mouse_input: Vec3;
     let mut movement_input = Vec3::zero();
 
-    let mut camera_angles = Vec3::zero();
-    let mut camera_origin = Vec3::zero();
+    let mut controller = CameraController::new(&scene_camera);
+    let mut viewer = Viewer::default();
+    if gpu {
+        viewer.backend = gpu_backend(&display);
+    }
+
+    let mut selected = None;
+    let mut cursor = LogicalPosition::new(0.0, 0.0);
+
+    let mut move_speed = bindings.speed;
+    let mut sprint = false;
+
+    // keyframes since recording started
+    let mut recording: Option<(Instant, CameraPath)> = None;
+
+    let mut show_stats = false;
+    let mut frame_times = VecDeque::new();
+    let mut rays_per_second = 0.0;
+    let mut path_length = 0.0;
 
     let mut closed = false;
     while !closed {
         mouse_input = Vec3::zero();
+        let mut click = None;
+        let mut edit_keys = Vec::new();
+        let mut toggle_grab = false;
+        let mut toggle_controller = false;
+        let mut toggle_recording = false;
+        let mut toggle_backend = false;
 
         events_loop.poll_events(|ev| match ev {
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 ..
             } => closed = true,
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                ..
+            } => display_size = size,
+            Event::WindowEvent {
+                event: WindowEvent::CursorMoved { position, .. },
+                ..
+            } => cursor = position,
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        state: ElementState::Pressed,
+                        button,
+                        ..
+                    },
+                ..
+            } => click = Some(button),
+            Event::WindowEvent {
+                event: WindowEvent::MouseWheel { delta, .. },
+                ..
+            } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                };
+                move_speed *= 1.1f32.powf(lines);
+            }
             Event::DeviceEvent { event, .. } => match event {
                 DeviceEvent::MouseMotion { delta: mouse_delta } => {
                     let mouse_delta = Vec3::new(mouse_delta.0 as _, mouse_delta.1 as _, 0.0f32);
@@ -104,18 +213,32 @@ fn main() {
                         ElementState::Released => -1.0f32,
                     };
 
-                    match key {
-                        VirtualKeyCode::W => movement_input += sign * Vec3::unit_z(),
-                        VirtualKeyCode::S => movement_input += -sign * Vec3::unit_z(),
+                    let pressed = state == ElementState::Pressed;
+                    match bindings.action(key) {
+                        Some(Action::Forward) => movement_input += -sign * Vec3::unit_z(),
+                        Some(Action::Back) => movement_input += sign * Vec3::unit_z(),
+
+                        Some(Action::Left) => movement_input += -sign * Vec3::unit_x(),
+                        Some(Action::Right) => movement_input += sign * Vec3::unit_x(),
+
+                        Some(Action::Down) => movement_input += -sign * Vec3::unit_y(),
+                        Some(Action::Up) => movement_input += sign * Vec3::unit_y(),
 
-                        VirtualKeyCode::A => movement_input += sign * Vec3::unit_x(),
-                        VirtualKeyCode::D => movement_input += -sign * Vec3::unit_x(),
+                        Some(Action::Sprint) => sprint = pressed,
 
-                        VirtualKeyCode::Q => movement_input += -sign * Vec3::unit_y(),
-                        VirtualKeyCode::E => movement_input += sign * Vec3::unit_y(),
+                        Some(Action::ToggleDenoise) if pressed => viewer.denoise = !viewer.denoise,
+                        Some(Action::ToggleGrab) if pressed => toggle_grab = true,
+                        Some(Action::ToggleController) if pressed => toggle_controller = true,
+                        Some(Action::ToggleProgressive) if pressed => progressive = !progressive,
+                        Some(Action::ToggleStats) if pressed => show_stats = !show_stats,
+                        Some(Action::ToggleRecording) if pressed => toggle_recording = true,
+                        Some(Action::ToggleBackend) if pressed => toggle_backend = true,
+                        Some(Action::Save) if pressed => edit_keys.push(key),
 
                         // System Input
-                        VirtualKeyCode::Escape => closed = true,
+                        Some(Action::Quit) => closed = true,
+
+                        None if pressed => edit_keys.push(key),
                         _ => {}
                     }
                     movement_input = movement_input.min(Vec3::one()).max(-Vec3::one());
@@ -127,94 +250,688 @@ fn main() {
             }
         });
 
-        let mut can_accumulate = true;
-
         let instant_this_frame = Instant::now();
         let _frame_time = (instant_this_frame - instant_start).as_nanos() as f32 * 1e-9f32;
         let dt = (instant_this_frame - instant_last_frame).as_nanos() as f32 * 1e-9f32;
         instant_last_frame = instant_this_frame;
 
-        let camera_angles_delta = Vec3::new(-10.0, 10.0, 0.0) * mouse_input * dt;
-        camera_angles += camera_angles_delta;
-        camera_angles.set_y(camera_angles.y().clamp(-85.0, 85.0));
-        let camera_rotation = Quat::from_rotation_ypr(
-            deg(camera_angles.x()),
-            deg(camera_angles.y()),
-            deg(camera_angles.z()),
-        );
+        if toggle_grab {
+            grabbed = !grabbed;
+            let gl_window = display.gl_window();
+            let window: &Window = gl_window.window();
+            window.grab_cursor(grabbed).unwrap();
+            window.hide_cursor(grabbed);
+        }
+
+        let moving = (grabbed && mouse_input.length_squared() > 0.0)
+            || movement_input.length_squared() > 0.0;
+
+        // a free cursor is for picking, not for looking around
+        let look = if grabbed {
+            -bindings.sensitivity * mouse_input * dt
+        } else {
+            Vec3::zero()
+        };
+        let speed = if sprint { 4.0 } else { 1.0 } * move_speed;
+        controller.update(look, movement_input, speed, dt);
+
+        if toggle_backend {
+            viewer.backend = match viewer.backend {
+                Backend::Cpu => gpu_backend(&display),
+                Backend::Gpu(_) => Backend::Cpu,
+            };
+            viewer.accumulator.reset();
+        }
+
+        if toggle_recording {
+            recording = match recording.take() {
+                Some((_, path)) => {
+                    match path.save(&record_path) {
+                        Ok(()) => println!(
+                            "saved {} keyframes to {}",
+                            path.keyframes.len(),
+                            record_path
+                        ),
+                        Err(error) => eprintln!("couldn't save {}: {}", record_path, error),
+                    }
+                    None
+                }
+                None => Some((Instant::now(), CameraPath::default())),
+            };
+        }
+        if let Some((start, path)) = &mut recording {
+            path.keyframes.push(Keyframe {
+                time: (Instant::now() - *start).as_nanos() as f32 * 1e-9,
+                origin: controller.origin,
+                angles: controller.angles,
+            });
+        }
 
-        let camera_movement_delta = 1.0 * movement_input * dt;
-        camera_origin += camera_rotation * camera_movement_delta;
+        let aspect = display_size.width as f32 / display_size.height as f32;
+        let camera = controller.camera(&scene_camera, aspect, &scene.world);
 
-        if camera_angles_delta.length_squared() > 0.0
-            || camera_movement_delta.length_squared() > 0.0
-        {
-            can_accumulate = false;
+        if toggle_controller {
+            // orbit the selection, or whatever is in the middle of the view
+            let target = match selected.and_then(|object| scene.sphere(object)) {
+                Some(sphere) => sphere.center,
+                None => {
+                    let ray = camera.pinhole_ray(glam::Vec2::new(0.5, 0.5));
+                    match scene.world.hit(&ray, [1e-3, f32::MAX]) {
+                        Some(hit) => hit.pos,
+                        None => camera.origin - camera.focus_dist * camera.w,
+                    }
+                }
+            };
+            controller.toggle_orbit(target);
+        }
+
+        // left click selects the sphere under the cursor, any other button deselects,
+        // through the pinhole so objects out of focus are picked as precisely
+        if let Some(button) = click {
+            selected = match button {
+                MouseButton::Left => {
+                    let uv = glam::Vec2::new(
+                        (cursor.x / display_size.width) as f32,
+                        1.0 - (cursor.y / display_size.height) as f32,
+                    );
+                    scene
+                        .world
+                        .hit(&camera.pinhole_ray(uv), [1e-3, f32::MAX])
+                        .map(|hit| hit.object)
+                        .filter(|&object| scene.sphere(object).is_some())
+                }
+                _ => None,
+            };
+        }
+
+        for key in edit_keys {
+            if bindings.action(key) == Some(Action::Save) {
+                let path = scene_path.as_ref().map_or("scene.scene", String::as_str);
+                match scene_file::save(path, &scene) {
+                    Ok(()) => println!("saved {}", path),
+                    Err(error) => eprintln!("couldn't save {}: {}", path, error),
+                }
+            } else if edit_sphere(&mut scene, &mut selected, &camera, key) {
+                viewer.accumulator.reset();
+            }
         }
 
-        let camera = &Camera::new(
-            camera_origin,
-            camera_origin + camera_rotation * Vec3::unit_z(),
-            Vec3::new(0.0, 1.0, 0.0),
-            100.0,
-            display_size.width as f32 / display_size.height as f32,
-            0.025,
-            1.0,
+        // progressive mode renders every pixel and keeps accumulating, the preview drops
+        // resolution to keep up while the camera moves
+        let scale = if progressive { 1.0 } else { resolution_scale };
+        let resolution: (u32, u32) = (
+            ((display_size.width * scale).round() as u32).max(1),
+            ((display_size.height * scale).round() as u32).max(1),
         );
 
         let instant_before_render = Instant::now();
 
-        let render = SrgbTexture2d::with_format(
+        let (color, rays) = viewer.frame(&camera, &scene, (resolution.0 as _, resolution.1 as _));
+
+        // linear radiance, tonemapped when presented
+        let mut radiance = Vec::with_capacity(3 * color.len());
+        for c in color {
+            radiance.extend_from_slice(&[c.x(), c.y(), c.z()]);
+        }
+        let render = Texture2d::with_format(
             &display,
-            glium::texture::RawImage2d::from_raw_rgba(
-                tracer::trace::image(camera, (resolution.0 as _, resolution.1 as _), 1, 50),
-                resolution,
-            ),
-            SrgbFormat::U8U8U8U8,
+            RawImage2d::from_raw_rgb(radiance, resolution),
+            UncompressedFloatFormat::F32F32F32F32,
             MipmapsOption::NoMipmap,
         )
         .unwrap();
 
-        // composite render with accumulated image
-        let mut composite_surface = SimpleFrameBuffer::new(&display, &composite).unwrap();
-        composite_surface
+        // bit to present
+        let mut target = display.draw();
+        target
             .draw(
                 &geometry.0,
                 &geometry.1,
                 &composite_program,
-                &uniform!(
-                render_tex: &render,
-                accumulation_tex: &accumulation,
-                history_alpha: if can_accumulate { 0.9f32 } else { 0.0f32 }
-                ),
+                &uniform!(render_tex: &render),
                 &Default::default(),
             )
             .unwrap();
 
-        // update accumulated image
-        let accumulation_surface = SimpleFrameBuffer::new(&display, &accumulation).unwrap();
-        composite_surface.fill(&accumulation_surface, MagnifySamplerFilter::Linear);
+        if show_stats {
+            let frame_times: Vec<f32> = frame_times.iter().cloned().collect();
+            let stats = Stats {
+                frame_times: &frame_times,
+                rays_per_second,
+                path_length,
+                samples: viewer.accumulator.samples(),
+                camera: &camera,
+                angles: controller.angles,
+                controller: &controller.controller,
+                speed: move_speed,
+                resolution,
+                progressive,
+                denoise: viewer.denoise,
+                depth: viewer.depth,
+            };
+            let overlay = stats.draw();
 
-        // bit to present
-        let frame = display.draw();
-        composite_surface.fill(&frame, MagnifySamplerFilter::Linear);
-        frame.finish().unwrap();
+            // twice the size, the font is tiny
+            let (width, height) = (overlay.width as f64 * 2.0, overlay.height as f64 * 2.0);
+            let rect = [
+                -1.0f32,
+                1.0 - (2.0 * height / display_size.height) as f32,
+                -1.0 + (2.0 * width / display_size.width) as f32,
+                1.0,
+            ];
+            let overlay_tex = Texture2d::new(
+                &display,
+                RawImage2d::from_raw_rgba(
+                    overlay.pixels,
+                    (overlay.width as u32, overlay.height as u32),
+                ),
+            )
+            .unwrap();
+            target
+                .draw(
+                    &geometry.0,
+                    geometry.1,
+                    &overlay_program,
+                    &uniform!(
+                        overlay_tex: overlay_tex.sampled().magnify_filter(MagnifySamplerFilter::Nearest),
+                        rect: rect,
+                    ),
+                    &glium::DrawParameters {
+                        blend: glium::Blend::alpha_blending(),
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+        }
+        target.finish().unwrap();
 
         let instant_after_render = Instant::now();
         let render_time_in_seconds =
             (instant_after_render - instant_before_render).as_nanos() as f64 * 1e-9;
 
+        frame_times.push_back((render_time_in_seconds * 1e3) as f32);
+        if frame_times.len() > STATS_HISTORY {
+            frame_times.pop_front();
+        }
+        rays_per_second = rays.total() as f64 / render_time_in_seconds.max(1e-6);
+        path_length = rays.path_length();
+
+        // changing resolution restarts accumulation, so only while that's happening anyway
+        if moving && !progressive {
+            resolution_scale = rescale(resolution_scale, render_time_in_seconds);
+        }
+
         let gl_window = display.gl_window();
         let window: &Window = gl_window.window();
         window.set_title(&format!(
-            "Tracer | {:0.02}ms @ {}x{}",
+            "Tracer | {:0.02}ms @ {}x{} {} | {} spp{}{}{}{}",
             render_time_in_seconds * 1e3,
             resolution.0,
-            resolution.1
+            resolution.1,
+            if progressive {
+                "progressive"
+            } else {
+                "preview"
+            },
+            viewer.accumulator.samples(),
+            if viewer.denoise { " | denoised" } else { "" },
+            if let Backend::Gpu(_) = viewer.backend {
+                " | gpu"
+            } else {
+                ""
+            },
+            if recording.is_some() {
+                " | recording"
+            } else {
+                ""
+            },
+            match selected {
+                Some(object) => format!(" | sphere {} selected", object),
+                None => String::new(),
+            }
         ));
     }
 }
 
+/// Frames kept for the frame time graph.
+const STATS_HISTORY: usize = 120;
+
+/// What the statistics overlay shows.
+struct Stats<'a> {
+    /// Render times in milliseconds, oldest first.
+    frame_times: &'a [f32],
+    rays_per_second: f64,
+    path_length: f32,
+    samples: u32,
+    camera: &'a Camera,
+    angles: Vec3,
+    controller: &'a Controller,
+    speed: f32,
+    resolution: (u32, u32),
+    progressive: bool,
+    denoise: bool,
+    depth: i32,
+}
+
+impl<'a> Stats<'a> {
+    fn draw(&self) -> Overlay {
+        const TEXT: [u8; 4] = [255, 255, 255, 255];
+        const GRAPH: [u8; 4] = [110, 200, 90, 255];
+        const GRAPH_HEIGHT: usize = 32;
+
+        let width = 42 * CHAR_WIDTH + 4;
+        let height = 7 * LINE_HEIGHT + GRAPH_HEIGHT + 6;
+        let mut overlay = Overlay::new(width, height, [0, 0, 0, 160]);
+
+        let last = self.frame_times.last().cloned().unwrap_or(0.0);
+        let average = self.frame_times.iter().sum::<f32>() / self.frame_times.len().max(1) as f32;
+        let max = self.frame_times.iter().cloned().fold(1.0, f32::max);
+        overlay.text(
+            2,
+            2,
+            &format!("frame {:.1} ms (avg {:.1}, max {:.1})", last, average, max),
+            TEXT,
+        );
+        overlay.graph(
+            2,
+            2 + LINE_HEIGHT,
+            STATS_HISTORY,
+            GRAPH_HEIGHT,
+            self.frame_times,
+            max,
+            GRAPH,
+        );
+
+        let controller = match self.controller {
+            Controller::Fly => "fly",
+            Controller::Orbit { .. } => "orbit",
+        };
+        let origin = self.camera.origin;
+        let lines = [
+            format!(
+                "{:.2} Mrays/s, path length {:.2}",
+                self.rays_per_second * 1e-6,
+                self.path_length
+            ),
+            format!("{} samples accumulated", self.samples),
+            format!(
+                "pos {:.2}, {:.2}, {:.2}",
+                origin.x(),
+                origin.y(),
+                origin.z()
+            ),
+            format!("yaw {:.1} pitch {:.1}", self.angles.x(), self.angles.y()),
+            format!(
+                "{}x{} {}, depth {}",
+                self.resolution.0,
+                self.resolution.1,
+                if self.progressive {
+                    "progressive"
+                } else {
+                    "preview"
+                },
+                self.depth
+            ),
+            format!(
+                "denoise {}, {} speed {:.2}",
+                if self.denoise { "on" } else { "off" },
+                controller,
+                self.speed
+            ),
+        ];
+        for (index, line) in lines.iter().enumerate() {
+            let y = 4 + GRAPH_HEIGHT + (index + 1) * LINE_HEIGHT;
+            overlay.text(2, y, line, TEXT);
+        }
+        overlay
+    }
+}
+
+/// The camera the viewer starts from without a scene file, looking down -z and keeping
+/// what it looks at in focus.
+fn default_camera() -> Camera {
+    let mut camera = Camera::from_rotation(Vec3::zero(), Quat::identity(), 100.0, 1.0, 0.025, 1.0);
+    camera.refocus = true;
+    camera
+}
+
+/// The compute shader tracer on the window's context, or the CPU if the driver can't run it.
+fn gpu_backend(display: &Display) -> Backend {
+    match GpuTracer::new(display) {
+        Ok(tracer) => Backend::Gpu(Box::new(tracer)),
+        Err(error) => {
+            eprintln!("staying on the CPU: {}", error);
+            Backend::Cpu
+        }
+    }
+}
+
+/// Replays `path` through the viewer without a window, writing every frame as a numbered
+/// PNG into `output`.
+fn run_headless(
+    scene: &Scene,
+    base_camera: &Camera,
+    path: &CameraPath,
+    resolution: (usize, usize),
+    fps: f32,
+    output: &str,
+) -> io::Result<()> {
+    fs::create_dir_all(output)?;
+    Viewer::default().replay(
+        scene,
+        base_camera,
+        path,
+        resolution,
+        fps,
+        |index, pixels| {
+            let file = format!("{}/frame_{:04}.png", output, index);
+            image::save_buffer(
+                &file,
+                pixels,
+                resolution.0 as u32,
+                resolution.1 as u32,
+                image::RGBA(8),
+            )?;
+            println!("{}", file);
+            Ok(())
+        },
+    )
+}
+
+/// Reports a file that couldn't be loaded and gives up.
+fn fail(path: &str, error: io::Error) -> ! {
+    eprintln!("couldn't load {}: {}", path, error);
+    std::process::exit(1)
+}
+
+/// Render time the preview resolution is scaled to reach, in seconds.
+const TARGET_FRAME_TIME: f64 = 1.0 / 30.0;
+
+/// Resolution scale that should bring the render time to the target. Render time goes
+/// with the number of pixels, the square of the scale.
+fn rescale(scale: f64, render_time: f64) -> f64 {
+    let ideal = scale * (TARGET_FRAME_TIME / render_time.max(1e-6)).sqrt();
+    // only part of the way, timings are noisy
+    (scale + 0.25 * (ideal - scale)).clamp(0.05, 1.0)
+}
+
+/// Applies an editing key to the selected sphere, returning whether the scene changed.
+///
+/// Arrows and page up/down move it along the view, +/- scale it, M cycles through the
+/// materials, 1/2/3 brighten the red, green and blue albedo, [ and ] change the fuzz or
+/// index of refraction, C duplicates and Delete removes it.
+fn edit_sphere(
+    scene: &mut Scene,
+    selected: &mut Option<usize>,
+    camera: &Camera,
+    key: VirtualKeyCode,
+) -> bool {
+    const STEP: f32 = 0.05;
+
+    let index = match *selected {
+        Some(index) => index,
+        None => return false,
+    };
+    let sphere = match scene.sphere_mut(index) {
+        Some(sphere) => sphere,
+        None => return false,
+    };
+
+    match key {
+        VirtualKeyCode::Left => sphere.center -= STEP * camera.u,
+        VirtualKeyCode::Right => sphere.center += STEP * camera.u,
+        VirtualKeyCode::Down => sphere.center -= STEP * camera.v,
+        VirtualKeyCode::Up => sphere.center += STEP * camera.v,
+        VirtualKeyCode::PageUp => sphere.center -= STEP * camera.w,
+        VirtualKeyCode::PageDown => sphere.center += STEP * camera.w,
+
+        VirtualKeyCode::Add | VirtualKeyCode::Equals => sphere.radius *= 1.1,
+        VirtualKeyCode::Subtract | VirtualKeyCode::Minus => sphere.radius /= 1.1,
+
+        VirtualKeyCode::M => {
+            let albedo = sphere.mat.albedo();
+            sphere.mat = match sphere.mat {
+                Material::Lambert { .. } => Material::Metal { albedo, fuzz: 0.1 },
+                Material::Metal { .. } => Material::Dielectric {
+                    albedo,
+                    ref_idx: 1.5,
+                },
+                Material::Dielectric { .. } | Material::Isotropic { .. } => {
+                    Material::Lambert { albedo }
+                }
+            }
+        }
+        VirtualKeyCode::Key1 | VirtualKeyCode::Key2 | VirtualKeyCode::Key3 => {
+            let mut albedo = sphere.mat.albedo();
+            let brighten = |c: f32| if c >= 0.95 { 0.0 } else { c + 0.1 };
+            match key {
+                VirtualKeyCode::Key1 => albedo.set_x(brighten(albedo.x())),
+                VirtualKeyCode::Key2 => albedo.set_y(brighten(albedo.y())),
+                _ => albedo.set_z(brighten(albedo.z())),
+            }
+            sphere.mat = sphere.mat.with_albedo(albedo);
+        }
+        VirtualKeyCode::LBracket | VirtualKeyCode::RBracket => {
+            let step = if key == VirtualKeyCode::LBracket {
+                -0.05
+            } else {
+                0.05
+            };
+            sphere.mat = match sphere.mat {
+                Material::Metal { albedo, fuzz } => Material::Metal {
+                    albedo,
+                    fuzz: (fuzz + step).clamp(0.0, 1.0),
+                },
+                Material::Dielectric { albedo, ref_idx } => Material::Dielectric {
+                    albedo,
+                    ref_idx: (ref_idx + step).max(1.0),
+                },
+                mat => mat,
+            }
+        }
+
+        VirtualKeyCode::C => {
+            let mut copy: Sphere = sphere.clone();
+            copy.center += 2.0 * copy.radius.abs() * camera.u;
+            scene.world.push(Box::new(copy));
+            *selected = Some(scene.world.len() - 1);
+        }
+        VirtualKeyCode::Delete => {
+            scene.remove(index);
+            *selected = None;
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Everything a key can be bound to.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Action {
+    Forward,
+    Back,
+    Left,
+    Right,
+    Down,
+    Up,
+    Sprint,
+    ToggleGrab,
+    ToggleController,
+    ToggleDenoise,
+    ToggleProgressive,
+    ToggleStats,
+    ToggleRecording,
+    ToggleBackend,
+    Save,
+    Quit,
+}
+
+impl Action {
+    const ALL: [Action; 16] = [
+        Action::Forward,
+        Action::Back,
+        Action::Left,
+        Action::Right,
+        Action::Down,
+        Action::Up,
+        Action::Sprint,
+        Action::ToggleGrab,
+        Action::ToggleController,
+        Action::ToggleDenoise,
+        Action::ToggleProgressive,
+        Action::ToggleStats,
+        Action::ToggleRecording,
+        Action::ToggleBackend,
+        Action::Save,
+        Action::Quit,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Action::Forward => "forward",
+            Action::Back => "back",
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::Down => "down",
+            Action::Up => "up",
+            Action::Sprint => "sprint",
+            Action::ToggleGrab => "grab",
+            Action::ToggleController => "controller",
+            Action::ToggleDenoise => "denoise",
+            Action::ToggleProgressive => "progressive",
+            Action::ToggleStats => "stats",
+            Action::ToggleRecording => "record",
+            Action::ToggleBackend => "backend",
+            Action::Save => "save",
+            Action::Quit => "quit",
+        }
+    }
+}
+
+/// Key bindings and camera speeds, loaded from a file of `name value` lines where `#`
+/// starts a comment:
+///
+/// ```text
+/// forward Up
+/// sprint LShift
+/// grab Tab
+/// speed 2
+/// sensitivity 10
+/// ```
+///
+/// Keys are named after `VirtualKeyCode` variants, and an action keeps its default key
+/// unless the file binds it. Keys bound to nothing are left for editing the selection.
+/// There are no gamepad bindings, glutin doesn't report gamepads.
+struct Bindings {
+    keys: HashMap<VirtualKeyCode, Action>,
+    /// Movement speed in units per second, the scroll wheel changes it while running.
+    speed: f32,
+    /// Degrees turned per unit of mouse motion per second.
+    sensitivity: f32,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let keys = [
+            (VirtualKeyCode::W, Action::Forward),
+            (VirtualKeyCode::S, Action::Back),
+            (VirtualKeyCode::A, Action::Left),
+            (VirtualKeyCode::D, Action::Right),
+            (VirtualKeyCode::Q, Action::Down),
+            (VirtualKeyCode::E, Action::Up),
+            (VirtualKeyCode::LShift, Action::Sprint),
+            (VirtualKeyCode::Tab, Action::ToggleGrab),
+            (VirtualKeyCode::O, Action::ToggleController),
+            (VirtualKeyCode::F, Action::ToggleDenoise),
+            (VirtualKeyCode::P, Action::ToggleProgressive),
+            (VirtualKeyCode::F1, Action::ToggleStats),
+            (VirtualKeyCode::R, Action::ToggleRecording),
+            (VirtualKeyCode::G, Action::ToggleBackend),
+            (VirtualKeyCode::F5, Action::Save),
+            (VirtualKeyCode::Escape, Action::Quit),
+        ];
+        Bindings {
+            keys: keys.iter().cloned().collect(),
+            speed: 1.0,
+            sensitivity: 10.0,
+        }
+    }
+}
+
+impl Bindings {
+    fn load(path: &str) -> io::Result<Bindings> {
+        let mut bindings = Bindings::default();
+
+        for (index, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", index + 1, message),
+                )
+            };
+
+            let mut tokens = line.split_whitespace();
+            let (name, value) = match (tokens.next(), tokens.next()) {
+                (Some(name), Some(value)) => (name, value),
+                _ => {
+                    return Err(error(format!(
+                        "expected a name and a value, found {}",
+                        line
+                    )))
+                }
+            };
+            match name {
+                "speed" | "sensitivity" => {
+                    let number = value
+                        .parse()
+                        .map_err(|_| error(format!("{} isn't a number", value)))?;
+                    if name == "speed" {
+                        bindings.speed = number;
+                    } else {
+                        bindings.sensitivity = number;
+                    }
+                }
+                _ => {
+                    let action = Action::ALL
+                        .iter()
+                        .cloned()
+                        .find(|action| action.name() == name)
+                        .ok_or_else(|| error(format!("unknown action {}", name)))?;
+                    let key = key_from_name(value)
+                        .ok_or_else(|| error(format!("unknown key {}", value)))?;
+                    bindings.keys.retain(|_, bound| *bound != action);
+                    bindings.keys.insert(key, action);
+                }
+            }
+        }
+        Ok(bindings)
+    }
+
+    fn action(&self, key: VirtualKeyCode) -> Option<Action> {
+        self.keys.get(&key).cloned()
+    }
+}
+
+fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+    use VirtualKeyCode::*;
+    const KEYS: &[VirtualKeyCode] = &[
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, Key0, Key1,
+        Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Numpad0, Numpad1, Numpad2, Numpad3,
+        Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9, F1, F2, F3, F4, F5, F6, F7, F8, F9,
+        F10, F11, F12, Escape, Tab, Space, Return, Back, Delete, Insert, Home, End, PageUp,
+        PageDown, Left, Right, Up, Down, LShift, RShift, LControl, RControl, LAlt, RAlt, Minus,
+        Equals, Add, Subtract, LBracket, RBracket, Comma, Period, Slash, Semicolon, Apostrophe,
+        Backslash, Grave,
+    ];
+    KEYS.iter()
+        .cloned()
+        .find(|key| format!("{:?}", key) == name)
+}
+
 fn create_fullscreen_geometry(display: &Display) -> (VertexBuffer<Vertex>, NoIndices) {
     implement_vertex!(Vertex, attr_pos, attr_uv);
     let vertices = vec![
@@ -240,7 +957,7 @@ fn create_fullscreen_geometry(display: &Display) -> (VertexBuffer<Vertex>, NoInd
 fn create_window(size: LogicalSize) -> (Display, EventsLoop) {
     let wb = WindowBuilder::new()
         .with_dimensions(size)
-        .with_resizable(false)
+        .with_resizable(true)
         .with_title("Tracer");
     let cb = ContextBuilder::new()
         .with_gl(GlRequest::Latest)
@@ -283,16 +1000,51 @@ fn compile_composite_shader(display: &Display) -> Program {
         #version 460
 
         layout(binding = 0) uniform sampler2D render_tex;
-        layout(binding = 2) uniform sampler2D accumulation_tex;
 
-        layout(location = 0) uniform float history_alpha;
+        layout(location = 2) in vec2 uv;
+
+        layout(location = 0) out vec4 color;
+
+        void main() {
+            // the backbuffer is sRGB, so clamping is all the tonemapping left to do
+            vec3 radiance = texture(render_tex, uv).rgb;
+            color = vec4(clamp(radiance, 0.0, 1.0), 1.0);
+        }
+    "#;
+    glium::Program::from_source(display, vertex, fragment, None).unwrap()
+}
+
+fn compile_overlay_shader(display: &Display) -> Program {
+    // the fullscreen triangle squeezed into `rect`, so the part with uv in [0, 1]² covers it
+    let vertex = r#"
+        #version 460
+
+        layout(location = 0) in vec2 attr_pos;
+        layout(location = 1) in vec2 attr_uv;
+
+        layout(location = 0) uniform vec4 rect;
+
+        layout(location = 2) out vec2 uv;
+
+        void main() {
+            uv = attr_uv;
+            gl_Position = vec4(mix(rect.xy, rect.zw, attr_uv), 0.0, 1.0);
+        }
+    "#;
+    let fragment = r#"
+        #version 460
+
+        layout(binding = 0) uniform sampler2D overlay_tex;
 
         layout(location = 2) in vec2 uv;
 
         layout(location = 0) out vec4 color;
 
         void main() {
-            color = mix(texture(render_tex, uv), texture(accumulation_tex, uv), history_alpha);
+            if (uv.x > 1.0 || uv.y > 1.0) {
+                discard;
+            }
+            color = texture(overlay_tex, uv);
         }
     "#;
     glium::Program::from_source(display, vertex, fragment, None).unwrap()
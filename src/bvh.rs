@@ -0,0 +1,346 @@
+use crate::aabb::Aabb;
+use crate::hit::{Hit, Hittable};
+use crate::ray::Ray;
+use crate::simd::{F32x4, Mask4, LANES};
+use crate::sphere::Sphere;
+use glam::Vec3;
+use std::cell::Cell;
+
+thread_local! {
+    static VISITS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Nodes of any `SphereBvh` opened by the traversals on this thread so far, once per
+/// packet for the rays that go down together. Renderers count the visits of their work
+/// from the difference before and after, so threads never share a counter.
+pub fn node_visits() -> u64 {
+    VISITS.with(Cell::get)
+}
+
+/// Deepest the traversal stack gets. Every level of the tree leaves at most one node
+/// behind on it and the halves are balanced, so this is far more than any tree needs.
+const STACK_SIZE: usize = 64;
+
+/// Up to `LANES` spheres laid out a component per vector, to be tested against a ray at
+/// once.
+struct SphereGroup {
+    center: [F32x4; 3],
+    motion: [F32x4; 3],
+    radius: F32x4,
+    /// Lanes that hold a sphere.
+    valid: Mask4,
+    /// Index of the sphere in every lane.
+    spheres: [usize; LANES],
+}
+
+impl SphereGroup {
+    fn new(spheres: &[Sphere], indices: &[usize]) -> SphereGroup {
+        let lane = |f: &dyn Fn(&Sphere) -> f32| {
+            let mut values = [0.0; LANES];
+            for (value, &index) in values.iter_mut().zip(indices) {
+                *value = f(&spheres[index]);
+            }
+            F32x4::new(values)
+        };
+
+        let mut valid = [false; LANES];
+        let mut lanes = [0; LANES];
+        for (lane, &index) in indices.iter().enumerate() {
+            valid[lane] = true;
+            lanes[lane] = index;
+        }
+
+        SphereGroup {
+            center: [
+                lane(&|s| s.center.x()),
+                lane(&|s| s.center.y()),
+                lane(&|s| s.center.z()),
+            ],
+            motion: [
+                lane(&|s| s.motion.x()),
+                lane(&|s| s.motion.y()),
+                lane(&|s| s.motion.z()),
+            ],
+            radius: lane(&|s| s.radius),
+            valid: Mask4::new(valid),
+            spheres: lanes,
+        }
+    }
+
+    /// The same test as `Sphere::hit` for every lane: the nearest root inside `range` and
+    /// the lanes that have one.
+    fn hit(&self, r: &Ray, range: [f32; 2]) -> (F32x4, Mask4) {
+        let time = F32x4::splat(r.time);
+        let oc = [
+            F32x4::splat(r.origin.x()) - (self.center[0] + self.motion[0] * time),
+            F32x4::splat(r.origin.y()) - (self.center[1] + self.motion[1] * time),
+            F32x4::splat(r.origin.z()) - (self.center[2] + self.motion[2] * time),
+        ];
+        let dir = [
+            F32x4::splat(r.dir.x()),
+            F32x4::splat(r.dir.y()),
+            F32x4::splat(r.dir.z()),
+        ];
+
+        let a = F32x4::splat(Vec3::dot(r.dir, r.dir));
+        let b = F32x4::splat(2.0) * (dir[0] * oc[0] + dir[1] * oc[1] + dir[2] * oc[2]);
+        let c = oc[0] * oc[0] + oc[1] * oc[1] + oc[2] * oc[2] - self.radius * self.radius;
+        let delta = b * b - F32x4::splat(4.0) * a * c;
+        let zero = F32x4::splat(0.0);
+
+        let root = delta.max(zero).sqrt();
+        let two_a = F32x4::splat(2.0) * a;
+        let near = (zero - b - root) / two_a;
+        let far = (zero - b + root) / two_a;
+
+        let (t_min, t_max) = (F32x4::splat(range[0]), F32x4::splat(range[1]));
+        let near_inside = near.gt(t_min) & near.lt(t_max);
+        let far_inside = far.gt(t_min) & far.lt(t_max);
+        let t = near_inside.select(near, far);
+        (t, self.valid & delta.gt(zero) & (near_inside | far_inside))
+    }
+}
+
+struct Node {
+    bounds: Aabb,
+    /// Group of a leaf, or second child of an interior node whose first child follows it.
+    index: usize,
+    leaf: bool,
+}
+
+/// Bounding volume hierarchy over spheres with a group of `LANES` spheres in every leaf,
+/// tested together with SIMD. Coherent rays, like the camera samples of a pixel, can also
+/// go down the tree together as a packet.
+pub struct SphereBvh {
+    spheres: Vec<Sphere>,
+    groups: Vec<SphereGroup>,
+    nodes: Vec<Node>,
+}
+
+impl SphereBvh {
+    /// Hits report the index of the sphere in `spheres`.
+    pub fn new(spheres: Vec<Sphere>) -> SphereBvh {
+        let mut bvh = SphereBvh {
+            spheres,
+            groups: Vec::new(),
+            nodes: Vec::new(),
+        };
+        if !bvh.spheres.is_empty() {
+            let bounds: Vec<Aabb> = bvh.spheres.iter().map(sphere_bounds).collect();
+            let mut indices: Vec<usize> = (0..bvh.spheres.len()).collect();
+            bvh.build(&mut indices, &bounds);
+        }
+        bvh
+    }
+
+    /// A tree over the world when it's made of nothing but spheres.
+    pub fn from_world(world: &[Box<dyn Hittable>]) -> Option<SphereBvh> {
+        let spheres = world
+            .iter()
+            .map(|object| object.as_ref().as_any().downcast_ref::<Sphere>().cloned())
+            .collect::<Option<Vec<Sphere>>>()?;
+        Some(SphereBvh::new(spheres))
+    }
+
+    fn build(&mut self, indices: &mut [usize], bounds: &[Aabb]) -> usize {
+        let node = self.nodes.len();
+        let node_bounds = indices[1..]
+            .iter()
+            .fold(bounds[indices[0]], |total, &i| total.union(&bounds[i]));
+
+        if indices.len() <= LANES {
+            self.nodes.push(Node {
+                bounds: node_bounds,
+                index: self.groups.len(),
+                leaf: true,
+            });
+            self.groups.push(SphereGroup::new(&self.spheres, indices));
+            return node;
+        }
+        self.nodes.push(Node {
+            bounds: node_bounds,
+            index: 0,
+            leaf: false,
+        });
+
+        // split along the axis the centers spread the most, keeping the first half's
+        // leaves full
+        let centers = indices[1..].iter().fold(
+            Aabb::new(bounds[indices[0]].center(), bounds[indices[0]].center()),
+            |total, &i| total.union(&Aabb::new(bounds[i].center(), bounds[i].center())),
+        );
+        let size = centers.size();
+        let axis = if size.x() >= size.y() && size.x() >= size.z() {
+            0
+        } else if size.y() >= size.z() {
+            1
+        } else {
+            2
+        };
+        let coordinate = |i: usize| match axis {
+            0 => bounds[i].center().x(),
+            1 => bounds[i].center().y(),
+            _ => bounds[i].center().z(),
+        };
+        indices.sort_by(|&a, &b| coordinate(a).partial_cmp(&coordinate(b)).unwrap());
+
+        let middle = ((indices.len() / 2).div_ceil(LANES) * LANES).min(indices.len() - 1);
+        let (first, second) = indices.split_at_mut(middle);
+        self.build(first, bounds);
+        self.nodes[node].index = self.build(second, bounds);
+        node
+    }
+
+    /// Sphere of the closest hit along `r`.
+    fn closest(&self, r: &Ray, range: [f32; 2]) -> Option<usize> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let inv_dir = r.dir.reciprocal();
+        let mut closest = None;
+        let mut t_max = range[1];
+
+        let mut stack = [0; STACK_SIZE];
+        let mut len = 1;
+        let mut visits = 0;
+        while len > 0 {
+            len -= 1;
+            visits += 1;
+            let index = stack[len];
+            let node = &self.nodes[index];
+            if !slab(&node.bounds, r.origin, inv_dir, [range[0], t_max]) {
+                continue;
+            }
+
+            if node.leaf {
+                let group = &self.groups[node.index];
+                let (t, mask) = group.hit(r, [range[0], t_max]);
+                for (lane, &t) in t.to_array().iter().enumerate() {
+                    if mask.test(lane) && t < t_max {
+                        t_max = t;
+                        closest = Some(group.spheres[lane]);
+                    }
+                }
+            } else {
+                debug_assert!(len + 2 <= STACK_SIZE, "SphereBvh traversal stack overflow");
+                stack[len] = node.index;
+                stack[len + 1] = index + 1;
+                len += 2;
+            }
+        }
+        VISITS.with(|total| total.set(total.get() + visits));
+        closest
+    }
+
+    /// Closest hits of up to `LANES` rays, traced down the tree together: a node is opened
+    /// when any of them crosses its bounds. Pays off when the rays are coherent.
+    pub fn hit_packet(&self, rays: &[Ray], range: [f32; 2]) -> Vec<Option<Hit>> {
+        assert!(rays.len() <= LANES);
+        let mut closest = [None; LANES];
+        let mut t_max = [f32::NEG_INFINITY; LANES];
+        for t in t_max.iter_mut().take(rays.len()) {
+            *t = range[1];
+        }
+
+        let lane = |f: &dyn Fn(&Ray) -> f32| {
+            let mut values = [0.0; LANES];
+            for (value, r) in values.iter_mut().zip(rays) {
+                *value = f(r);
+            }
+            F32x4::new(values)
+        };
+        let origin = [
+            lane(&|r| r.origin.x()),
+            lane(&|r| r.origin.y()),
+            lane(&|r| r.origin.z()),
+        ];
+        let inv_dir = [
+            lane(&|r| 1.0 / r.dir.x()),
+            lane(&|r| 1.0 / r.dir.y()),
+            lane(&|r| 1.0 / r.dir.z()),
+        ];
+        let t_min = F32x4::splat(range[0]);
+
+        let mut stack = [0; STACK_SIZE];
+        let mut len = if self.nodes.is_empty() { 0 } else { 1 };
+        let mut visits = 0;
+        while len > 0 {
+            len -= 1;
+            visits += 1;
+            let index = stack[len];
+            let node = &self.nodes[index];
+
+            // the slab test for every ray of the packet, rays done with get an empty range
+            let (min, max) = (node.bounds.min, node.bounds.max);
+            let slabs = [(min.x(), max.x()), (min.y(), max.y()), (min.z(), max.z())];
+            let mut t_enter = t_min;
+            let mut t_exit = F32x4::new(t_max);
+            for (axis, &(min, max)) in slabs.iter().enumerate() {
+                let t0 = (F32x4::splat(min) - origin[axis]) * inv_dir[axis];
+                let t1 = (F32x4::splat(max) - origin[axis]) * inv_dir[axis];
+                t_enter = t_enter.max(t0.min(t1));
+                t_exit = t_exit.min(t0.max(t1));
+            }
+            let crossing = t_enter.lt(t_exit);
+            if !crossing.any() {
+                continue;
+            }
+
+            if node.leaf {
+                let group = &self.groups[node.index];
+                for (ray, r) in rays.iter().enumerate() {
+                    if !crossing.test(ray) {
+                        continue;
+                    }
+                    let (t, mask) = group.hit(r, [range[0], t_max[ray]]);
+                    for (lane, &t) in t.to_array().iter().enumerate() {
+                        if mask.test(lane) && t < t_max[ray] {
+                            t_max[ray] = t;
+                            closest[ray] = Some(group.spheres[lane]);
+                        }
+                    }
+                }
+            } else {
+                debug_assert!(len + 2 <= STACK_SIZE, "SphereBvh traversal stack overflow");
+                stack[len] = node.index;
+                stack[len + 1] = index + 1;
+                len += 2;
+            }
+        }
+        VISITS.with(|total| total.set(total.get() + visits));
+
+        rays.iter()
+            .zip(&closest)
+            .map(|(r, &sphere)| self.hit_sphere(r, range, sphere?))
+            .collect()
+    }
+
+    /// The full hit record, from the same test on the sphere alone.
+    fn hit_sphere(&self, r: &Ray, range: [f32; 2], sphere: usize) -> Option<Hit> {
+        let mut hit = self.spheres[sphere].hit(r, range)?;
+        hit.object = sphere;
+        Some(hit)
+    }
+}
+
+impl Hittable for SphereBvh {
+    fn hit(&self, r: &Ray, range: [f32; 2]) -> Option<Hit> {
+        let sphere = self.closest(r, range)?;
+        self.hit_sphere(r, range, sphere)
+    }
+}
+
+fn sphere_bounds(sphere: &Sphere) -> Aabb {
+    // hollow spheres have a negative radius
+    let extent = Vec3::splat(sphere.radius.abs());
+    let start = Aabb::new(sphere.center - extent, sphere.center + extent);
+    let end = sphere.center + sphere.motion;
+    start.union(&Aabb::new(end - extent, end + extent))
+}
+
+/// Slab test against precomputed reciprocal directions, see `Aabb::hit`.
+fn slab(bounds: &Aabb, origin: Vec3, inv_dir: Vec3, range: [f32; 2]) -> bool {
+    let t0 = (bounds.min - origin) * inv_dir;
+    let t1 = (bounds.max - origin) * inv_dir;
+    t0.min(t1).max_element().max(range[0]) < t0.max(t1).min_element().min(range[1])
+}
@@ -1,17 +1,114 @@
+use crate::distribution::Distribution2D;
+use crate::hit::Hittable;
 use crate::math::random_in_unit_disk;
 use crate::ray::Ray;
+use crate::rng;
 use glam::f32::Vec3;
-use glam::Vec2;
+use glam::{deg, Mat4, Quat, Vec2};
+use std::f32::consts::PI;
+use std::io;
+use std::sync::Arc;
 
+/// Maps image coordinates to pinhole rays. Rays are in the camera's local frame:
+/// x to the right, y up and looking down -z, with `uv` in [0, 1]² from the bottom left.
+pub trait CameraModel: Send + Sync {
+    fn local_ray(&self, uv: Vec2, aspect: f32) -> Ray;
+
+    /// Image coordinates of a point in the local frame, the inverse of `local_ray`. None
+    /// when the point can't be seen or the model has no inverse.
+    fn project(&self, _local: Vec3, _aspect: f32) -> Option<Vec2> {
+        None
+    }
+
+    /// The same model with another field of view in degrees, None when it has no field of
+    /// view to change.
+    fn with_fov(&self, _fov: f32) -> Option<Arc<dyn CameraModel>> {
+        None
+    }
+}
+
+#[derive(Clone)]
 pub struct Camera {
     pub origin: Vec3,
-    pub lower_left: Vec3,
-    pub horizontal: Vec3,
-    pub vertical: Vec3,
     pub u: Vec3,
     pub v: Vec3,
     pub w: Vec3,
+    pub aspect: f32,
     pub lens_radius: f32,
+    pub focus_dist: f32,
+    pub model: Arc<dyn CameraModel>,
+    pub aperture: Aperture,
+    /// Horizontal squeeze of an anamorphic lens, stretching the bokeh vertically.
+    pub anamorphic_squeeze: f32,
+    /// How much the lens barrel clips the aperture towards the frame edges, in [0, 1].
+    pub cat_eye: f32,
+    /// Rotation of the focus plane around the camera's x and y axes, in degrees.
+    pub focus_tilt: Vec2,
+    /// Whether to `autofocus` again every time the camera moves, rather than keeping
+    /// `focus_dist`.
+    pub refocus: bool,
+}
+
+/// Shape of the lens aperture, which is the shape of out of focus highlights.
+#[derive(Clone)]
+pub enum Aperture {
+    Circle,
+    /// Regular polygon from `blades` straight blades, `rotation` in degrees.
+    Polygon {
+        blades: u32,
+        rotation: f32,
+    },
+    Mask(Arc<ApertureMask>),
+}
+
+impl PartialEq for Aperture {
+    /// Masks are only equal to themselves, not to another mask of the same image.
+    fn eq(&self, other: &Aperture) -> bool {
+        match (self, other) {
+            (Aperture::Circle, Aperture::Circle) => true,
+            (
+                Aperture::Polygon { blades, rotation },
+                Aperture::Polygon {
+                    blades: other_blades,
+                    rotation: other_rotation,
+                },
+            ) => blades == other_blades && rotation == other_rotation,
+            (Aperture::Mask(mask), Aperture::Mask(other_mask)) => Arc::ptr_eq(mask, other_mask),
+            _ => false,
+        }
+    }
+}
+
+/// Grayscale image of the aperture, brighter texels let more light through.
+pub struct ApertureMask {
+    distribution: Distribution2D,
+}
+
+impl ApertureMask {
+    pub fn load(path: &str) -> io::Result<ApertureMask> {
+        let image = image::open(path)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?
+            .to_luma();
+        let (width, height) = image.dimensions();
+
+        // image rows go from the top, the lens is sampled from the bottom
+        let mut weights = Vec::with_capacity((width * height) as usize);
+        for y in (0..height).rev() {
+            for x in 0..width {
+                weights.push(f32::from(image.get_pixel(x, y).0[0]) / 255.0);
+            }
+        }
+
+        Ok(ApertureMask {
+            distribution: Distribution2D::new(&weights, width as usize),
+        })
+    }
+
+    /// Point on the mask, mapped to [-1, 1]².
+    pub fn sample(&self, u: Vec2) -> Vec2 {
+        let (p, _) = self.distribution.sample(u);
+        2.0 * p - Vec2::one()
+    }
 }
 
 impl Camera {
@@ -24,39 +121,413 @@ impl Camera {
         aperture: f32,
         focus_dist: f32,
     ) -> Camera {
-        let theta = vertical_fov.to_radians();
-        let half_height = (theta / 2.0).tan();
-        let half_width = aspect * half_height;
-
         let w = (origin - lookat).normalize();
         let u = Vec3::cross(up, w).normalize();
         let v = Vec3::cross(w, u);
 
+        Camera::from_basis(origin, u, v, w, vertical_fov, aspect, aperture, focus_dist)
+    }
+
+    /// Camera at `origin` looking down the local -z axis of `rotation`, with local y up.
+    pub fn from_rotation(
+        origin: Vec3,
+        rotation: Quat,
+        vertical_fov: f32,
+        aspect: f32,
+        aperture: f32,
+        focus_dist: f32,
+    ) -> Camera {
+        Camera::from_basis(
+            origin,
+            rotation * Vec3::unit_x(),
+            rotation * Vec3::unit_y(),
+            rotation * Vec3::unit_z(),
+            vertical_fov,
+            aspect,
+            aperture,
+            focus_dist,
+        )
+    }
+
+    /// Camera from a camera-to-world `transform`, with the same axes as `from_rotation`.
+    pub fn from_matrix(
+        transform: &Mat4,
+        vertical_fov: f32,
+        aspect: f32,
+        aperture: f32,
+        focus_dist: f32,
+    ) -> Camera {
+        Camera::from_basis(
+            transform.w_axis().truncate(),
+            transform.x_axis().truncate().normalize(),
+            transform.y_axis().truncate().normalize(),
+            transform.z_axis().truncate().normalize(),
+            vertical_fov,
+            aspect,
+            aperture,
+            focus_dist,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn from_basis(
+        origin: Vec3,
+        u: Vec3,
+        v: Vec3,
+        w: Vec3,
+        vertical_fov: f32,
+        aspect: f32,
+        aperture: f32,
+        focus_dist: f32,
+    ) -> Camera {
         let lens_radius = aperture / 2.0;
 
         Camera {
             origin,
-            lower_left: origin
-                - (focus_dist * half_width * u)
-                - (focus_dist * half_height * v)
-                - (focus_dist * w),
-            horizontal: 2.0 * half_width * focus_dist * u,
-            vertical: 2.0 * half_height * focus_dist * v,
-            lens_radius,
             u,
             v,
             w,
+            aspect,
+            lens_radius,
+            focus_dist,
+            model: Arc::new(Perspective { vertical_fov }),
+            aperture: Aperture::Circle,
+            anamorphic_squeeze: 1.0,
+            cat_eye: 0.0,
+            focus_tilt: Vec2::zero(),
+            refocus: false,
+        }
+    }
+
+    /// Moves the camera keeping its lens, with the same axes as `from_rotation`.
+    pub fn set_pose(&mut self, origin: Vec3, rotation: Quat) {
+        self.origin = origin;
+        self.u = rotation * Vec3::unit_x();
+        self.v = rotation * Vec3::unit_y();
+        self.w = rotation * Vec3::unit_z();
+    }
+
+    /// Changes the field of view of the camera model, if it has one.
+    pub fn set_fov(&mut self, fov: f32) {
+        if let Some(model) = self.model.with_fov(fov) {
+            self.model = model;
         }
     }
 
+    pub fn with_model(mut self, model: Arc<dyn CameraModel>) -> Camera {
+        self.model = model;
+        self
+    }
+
+    /// Perspective projection and aperture worked out from a physical camera body and lens.
+    pub fn with_lens(mut self, lens: &PhysicalLens) -> Camera {
+        self.model = Arc::new(Perspective {
+            vertical_fov: lens.vertical_fov(),
+        });
+        self.lens_radius = lens.aperture() / 2.0;
+        self
+    }
+
+    /// Focuses on whatever is at the center of the image, returning false and keeping the
+    /// current focus if the center ray escapes the scene.
+    pub fn autofocus(&mut self, world: &dyn Hittable) -> bool {
+        let ray = self.pinhole_ray(Vec2::new(0.5, 0.5));
+        match world.hit(&ray, [1e-3, f32::MAX]) {
+            Some(hit) => {
+                // distance to the focus plane, along the view axis
+                self.focus_dist = Vec3::dot(hit.pos - ray.origin, -self.w).max(1e-3);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Ray through `uv` ignoring the lens, so everything is in focus.
+    pub fn pinhole_ray(&self, uv: Vec2) -> Ray {
+        let pinhole = self.model.local_ray(uv, self.aspect);
+        Ray::new(
+            self.origin + self.to_world(pinhole.origin),
+            self.to_world(pinhole.dir),
+        )
+    }
+
+    /// Where `point` lands on the image, if the camera model can tell.
+    pub fn project(&self, point: Vec3) -> Option<Vec2> {
+        let offset = point - self.origin;
+        let local = Vec3::new(
+            Vec3::dot(offset, self.u),
+            Vec3::dot(offset, self.v),
+            Vec3::dot(offset, self.w),
+        );
+        self.model.project(local, self.aspect)
+    }
+
     pub fn get_ray(&self, uv: Vec2) -> Ray {
-        let sample_in_radius = self.lens_radius * random_in_unit_disk().extend(0.0);
-        let offset = self.u * sample_in_radius.x() + self.v * sample_in_radius.y();
+        let pinhole = self.model.local_ray(uv, self.aspect);
+        let (mut origin, mut dir) = (pinhole.origin, pinhole.dir);
+
+        if self.lens_radius > 0.0 {
+            // thin lens, everything on the focus plane is sharp
+            let focus_point = self.focus_point(origin, dir);
+            let sample_in_radius = self.lens_radius * self.sample_lens(uv);
+            origin += sample_in_radius.extend(0.0);
+            dir = focus_point - origin;
+        }
+
+        Ray::at_time(
+            self.origin + self.to_world(origin),
+            self.to_world(dir),
+            rng::random(),
+        )
+    }
+
+    /// Where the pinhole ray meets the focus plane, which sits `focus_dist` in front of the
+    /// lens and is tilted by `focus_tilt`.
+    fn focus_point(&self, origin: Vec3, dir: Vec3) -> Vec3 {
+        let normal =
+            Quat::from_rotation_ypr(deg(self.focus_tilt.y()), deg(self.focus_tilt.x()), deg(0.0))
+                * Vec3::unit_z();
+
+        let cos = Vec3::dot(normal, dir);
+        if cos < -1e-3 {
+            let t = Vec3::dot(normal, Vec3::new(0.0, 0.0, -self.focus_dist) - origin) / cos;
+            if t > 0.0 {
+                return origin + dir * t;
+            }
+        }
+        origin + dir.normalize() * self.focus_dist
+    }
+
+    /// Point on the aperture scaled to the unit disk, as seen from the film at `uv`.
+    fn sample_lens(&self, uv: Vec2) -> Vec2 {
+        // off axis the barrel hides part of the aperture, leaving the intersection
+        // with a disk shifted towards the center of the frame. The shift stops at the edge of
+        // the aperture, so the barrel always keeps the center of the lens in view.
+        let film = Vec2::new((2.0 * uv.x() - 1.0) * self.aspect, 2.0 * uv.y() - 1.0);
+        let mut barrel_center = -self.cat_eye * film;
+        if barrel_center.length_squared() > 1.0 {
+            barrel_center = barrel_center.normalize();
+        }
+
+        for _ in 0..64 {
+            let sample = self.sample_aperture();
+            if (sample - barrel_center).length_squared() <= 1.0 {
+                return Vec2::new(sample.x() / self.anamorphic_squeeze, sample.y());
+            }
+        }
+        // the center of the lens lies inside the barrel and, short of a mask with a hole in
+        // it, the aperture
+        Vec2::zero()
+    }
+
+    /// Uniform point on the aperture, in [-1, 1]² before the barrel clips it.
+    pub fn sample_aperture(&self) -> Vec2 {
+        match &self.aperture {
+            Aperture::Circle => random_in_unit_disk(),
+
+            Aperture::Polygon { blades, rotation } => {
+                // uniform point on one of the triangles fanning out from the center
+                let blades = (*blades).max(3);
+                let blade = (rng::random::<f32>() * blades as f32) as u32 % blades;
+                let angle = |i: u32| rotation.to_radians() + 2.0 * PI * i as f32 / blades as f32;
+                let (a0, a1) = (angle(blade), angle(blade + 1));
+
+                let (mut s, mut t) = (rng::random::<f32>(), rng::random::<f32>());
+                if s + t > 1.0 {
+                    s = 1.0 - s;
+                    t = 1.0 - t;
+                }
+                s * Vec2::new(a0.cos(), a0.sin()) + t * Vec2::new(a1.cos(), a1.sin())
+            }
+
+            Aperture::Mask(mask) => mask.sample(Vec2::new(rng::random(), rng::random())),
+        }
+    }
+
+    fn to_world(&self, local: Vec3) -> Vec3 {
+        self.u * local.x() + self.v * local.y() + self.w * local.z()
+    }
+}
+
+/// Camera body and lens, lengths are in millimeters and scene units are taken as meters.
+pub struct PhysicalLens {
+    pub sensor_width: f32,
+    pub sensor_height: f32,
+    pub focal_length: f32,
+    pub f_stop: f32,
+}
+
+impl PhysicalLens {
+    /// 36x24mm sensor.
+    pub fn full_frame(focal_length: f32, f_stop: f32) -> PhysicalLens {
+        PhysicalLens {
+            sensor_width: 36.0,
+            sensor_height: 24.0,
+            focal_length,
+            f_stop,
+        }
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.sensor_width / self.sensor_height
+    }
+
+    /// Vertical field of view in degrees.
+    pub fn vertical_fov(&self) -> f32 {
+        (2.0 * (self.sensor_height / (2.0 * self.focal_length)).atan()).to_degrees()
+    }
+
+    /// Aperture diameter in scene units.
+    pub fn aperture(&self) -> f32 {
+        self.focal_length / self.f_stop * 1e-3
+    }
+}
+
+pub struct Perspective {
+    pub vertical_fov: f32,
+}
+
+impl CameraModel for Perspective {
+    fn local_ray(&self, uv: Vec2, aspect: f32) -> Ray {
+        let half_height = (self.vertical_fov.to_radians() / 2.0).tan();
+        let half_width = aspect * half_height;
         Ray::new(
-            self.origin + offset,
-            (self.lower_left - self.origin - offset)
-                + (uv.x() * self.horizontal)
-                + (uv.y() * self.vertical),
+            Vec3::zero(),
+            Vec3::new(
+                (2.0 * uv.x() - 1.0) * half_width,
+                (2.0 * uv.y() - 1.0) * half_height,
+                -1.0,
+            ),
         )
     }
+
+    fn project(&self, local: Vec3, aspect: f32) -> Option<Vec2> {
+        if local.z() >= 0.0 {
+            return None;
+        }
+        let half_height = (self.vertical_fov.to_radians() / 2.0).tan();
+        let half_width = aspect * half_height;
+        Some(Vec2::new(
+            (local.x() / (-local.z() * half_width) + 1.0) / 2.0,
+            (local.y() / (-local.z() * half_height) + 1.0) / 2.0,
+        ))
+    }
+
+    fn with_fov(&self, fov: f32) -> Option<Arc<dyn CameraModel>> {
+        Some(Arc::new(Perspective { vertical_fov: fov }))
+    }
+}
+
+/// Parallel rays leaving a `height` tall window around the camera position.
+pub struct Orthographic {
+    pub height: f32,
+}
+
+impl CameraModel for Orthographic {
+    fn local_ray(&self, uv: Vec2, aspect: f32) -> Ray {
+        let half_height = self.height / 2.0;
+        let half_width = aspect * half_height;
+        Ray::new(
+            Vec3::new(
+                (2.0 * uv.x() - 1.0) * half_width,
+                (2.0 * uv.y() - 1.0) * half_height,
+                0.0,
+            ),
+            -Vec3::unit_z(),
+        )
+    }
+
+    fn project(&self, local: Vec3, aspect: f32) -> Option<Vec2> {
+        let half_height = self.height / 2.0;
+        let half_width = aspect * half_height;
+        Some(Vec2::new(
+            (local.x() / half_width + 1.0) / 2.0,
+            (local.y() / half_height + 1.0) / 2.0,
+        ))
+    }
+}
+
+/// Equidistant fisheye, the angle from the view axis grows linearly with the distance
+/// from the image center. `fov` covers the image height, in degrees.
+pub struct Fisheye {
+    pub fov: f32,
+}
+
+impl CameraModel for Fisheye {
+    fn local_ray(&self, uv: Vec2, aspect: f32) -> Ray {
+        let p = Vec2::new((2.0 * uv.x() - 1.0) * aspect, 2.0 * uv.y() - 1.0);
+        let r = p.length();
+        let theta = (r * self.fov.to_radians() / 2.0).min(PI);
+        let (sin_phi, cos_phi) = if r > 0.0 {
+            (p.y() / r, p.x() / r)
+        } else {
+            (0.0, 1.0)
+        };
+        Ray::new(
+            Vec3::zero(),
+            Vec3::new(theta.sin() * cos_phi, theta.sin() * sin_phi, -theta.cos()),
+        )
+    }
+
+    fn project(&self, local: Vec3, aspect: f32) -> Option<Vec2> {
+        let dir = local.normalize();
+        let theta = (-dir.z()).clamp(-1.0, 1.0).acos();
+        let r = theta / (self.fov.to_radians() / 2.0);
+        let phi = dir.y().atan2(dir.x());
+        Some(Vec2::new(
+            (r * phi.cos() / aspect + 1.0) / 2.0,
+            (r * phi.sin() + 1.0) / 2.0,
+        ))
+    }
+
+    fn with_fov(&self, fov: f32) -> Option<Arc<dyn CameraModel>> {
+        Some(Arc::new(Fisheye { fov }))
+    }
+}
+
+/// Full 360° by 180° latitude-longitude panorama, centered on the view direction.
+pub struct Equirectangular;
+
+impl CameraModel for Equirectangular {
+    fn local_ray(&self, uv: Vec2, _aspect: f32) -> Ray {
+        Ray::new(Vec3::zero(), panorama_dir(uv))
+    }
+
+    fn project(&self, local: Vec3, _aspect: f32) -> Option<Vec2> {
+        let dir = local.normalize();
+        let phi = dir.x().atan2(-dir.z());
+        let elevation = dir.y().clamp(-1.0, 1.0).asin();
+        Some(Vec2::new(phi / (2.0 * PI) + 0.5, elevation / PI + 0.5))
+    }
+}
+
+/// Omni-directional stereo panorama for VR, left eye on the top half of the image and
+/// right eye on the bottom half. Eyes sit on a circle `eye_separation` wide.
+pub struct StereoEquirectangular {
+    pub eye_separation: f32,
+}
+
+impl CameraModel for StereoEquirectangular {
+    fn local_ray(&self, uv: Vec2, _aspect: f32) -> Ray {
+        let (eye, v) = if uv.y() >= 0.5 {
+            (-1.0, 2.0 * uv.y() - 1.0)
+        } else {
+            (1.0, 2.0 * uv.y())
+        };
+        let uv = Vec2::new(uv.x(), v);
+
+        let phi = (uv.x() - 0.5) * 2.0 * PI;
+        let right = Vec3::new(phi.cos(), 0.0, phi.sin());
+        Ray::new(eye * 0.5 * self.eye_separation * right, panorama_dir(uv))
+    }
+}
+
+fn panorama_dir(uv: Vec2) -> Vec3 {
+    let phi = (uv.x() - 0.5) * 2.0 * PI;
+    let elevation = (uv.y() - 0.5) * PI;
+    Vec3::new(
+        elevation.cos() * phi.sin(),
+        elevation.sin(),
+        -elevation.cos() * phi.cos(),
+    )
 }
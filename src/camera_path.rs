@@ -0,0 +1,137 @@
+use crate::math::slerp;
+use glam::{deg, Quat, Vec3};
+use std::fs;
+use std::io;
+
+/// Where the camera was at some point in time, as a position and yaw, pitch and roll in
+/// degrees.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    pub time: f32,
+    pub origin: Vec3,
+    pub angles: Vec3,
+}
+
+/// Camera poses over time, stored one keyframe per line as `time x y z yaw pitch roll`
+/// with `#` starting a comment.
+#[derive(Default)]
+pub struct CameraPath {
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    pub fn load(path: &str) -> io::Result<CameraPath> {
+        let mut keyframes = Vec::new();
+        for (index, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let values = line
+                .split_whitespace()
+                .map(|value| value.parse::<f32>())
+                .collect::<Result<Vec<f32>, _>>();
+            // keyframes are sorted by time, which needs them all to compare
+            if let Ok(values) = &values {
+                if values.iter().any(|value| !value.is_finite()) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: values have to be finite", index + 1),
+                    ));
+                }
+            }
+            match values.as_ref().map(Vec::as_slice) {
+                Ok(&[time, x, y, z, yaw, pitch, roll]) => keyframes.push(Keyframe {
+                    time,
+                    origin: Vec3::new(x, y, z),
+                    angles: Vec3::new(yaw, pitch, roll),
+                }),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: expected time x y z yaw pitch roll", index + 1),
+                    ))
+                }
+            }
+        }
+
+        keyframes.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+        Ok(CameraPath { keyframes })
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut text = String::from("# time x y z yaw pitch roll\n");
+        for keyframe in &self.keyframes {
+            let (origin, angles) = (keyframe.origin, keyframe.angles);
+            text += &format!(
+                "{} {} {} {} {} {} {}\n",
+                keyframe.time,
+                origin.x(),
+                origin.y(),
+                origin.z(),
+                angles.x(),
+                angles.y(),
+                angles.z()
+            );
+        }
+        fs::write(path, text)
+    }
+
+    /// Time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// Position and rotation at `time`, held before the first keyframe and after the last.
+    /// Positions follow a Catmull-Rom spline through the keyframes and rotations are
+    /// slerped between them.
+    pub fn pose_at(&self, time: f32) -> Option<(Vec3, Quat)> {
+        let first = self.keyframes.first()?;
+        let last = self.keyframes.last()?;
+        if time <= first.time {
+            return Some((first.origin, first.rotation()));
+        }
+        if time >= last.time {
+            return Some((last.origin, last.rotation()));
+        }
+
+        let segment = self
+            .keyframes
+            .windows(2)
+            .position(|pair| time <= pair[1].time)
+            .unwrap();
+        let (a, b) = (self.keyframes[segment], self.keyframes[segment + 1]);
+        let duration = (b.time - a.time).max(1e-6);
+        let t = (time - a.time) / duration;
+
+        // cubic Hermite with the Catmull-Rom tangents, in units per second so uneven
+        // keyframe spacing doesn't kink the path
+        let (m0, m1) = (self.tangent(segment), self.tangent(segment + 1));
+        let (t2, t3) = (t * t, t * t * t);
+        let origin = (2.0 * t3 - 3.0 * t2 + 1.0) * a.origin
+            + (t3 - 2.0 * t2 + t) * duration * m0
+            + (-2.0 * t3 + 3.0 * t2) * b.origin
+            + (t3 - t2) * duration * m1;
+
+        Some((origin, slerp(a.rotation(), b.rotation(), t)))
+    }
+
+    /// Velocity through keyframe `index`, one sided at the ends.
+    fn tangent(&self, index: usize) -> Vec3 {
+        let before = self.keyframes[index.saturating_sub(1)];
+        let after = self.keyframes[(index + 1).min(self.keyframes.len() - 1)];
+        (after.origin - before.origin) / (after.time - before.time).max(1e-6)
+    }
+}
+
+impl Keyframe {
+    /// Rotation of a camera looking down its local -z axis, as in `Camera::set_pose`.
+    pub fn rotation(&self) -> Quat {
+        Quat::from_rotation_ypr(
+            deg(self.angles.x()),
+            deg(self.angles.y()),
+            deg(self.angles.z()),
+        )
+    }
+}
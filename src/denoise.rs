@@ -0,0 +1,145 @@
+use crate::frame::{Aov, Frame};
+use crate::math::luminance;
+use glam::Vec3;
+use rayon::prelude::*;
+
+/// Edge-avoiding à-trous wavelet filter guided by the albedo, normal and depth AOVs of a
+/// frame. Guides missing from the frame are ignored, so with none of them it degrades to
+/// a blur that only stops at changes in color.
+pub struct Denoiser {
+    /// Number of passes, each one doubles the reach of the filter.
+    pub iterations: usize,
+    /// How much luminance may differ, relative to the brighter pixel.
+    pub sigma_color: f32,
+    /// Exponent applied to the cosine between normals.
+    pub sigma_normal: f32,
+    /// How much depth may differ, relative to the depth of the center pixel.
+    pub sigma_depth: f32,
+    pub sigma_albedo: f32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            iterations: 5,
+            sigma_color: 1.0,
+            sigma_normal: 64.0,
+            sigma_depth: 0.1,
+            sigma_albedo: 0.1,
+        }
+    }
+}
+
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+impl Denoiser {
+    /// The filtered color of `frame`.
+    pub fn denoise(&self, frame: &Frame) -> Vec<Vec3> {
+        let (width, height) = frame.dimensions;
+        let albedo = frame.aov(Aov::Albedo);
+        let normal = frame.aov(Aov::Normal);
+        let depth = frame.aov(Aov::Depth);
+
+        // filter the lighting alone so textures and material edges stay sharp
+        let demodulate = |index: usize, color: Vec3| match albedo {
+            Some(albedo) => color / albedo[index].max(Vec3::splat(1e-3)),
+            None => color,
+        };
+        let mut lighting: Vec<Vec3> = frame
+            .color
+            .iter()
+            .enumerate()
+            .map(|(index, &color)| demodulate(index, color))
+            .collect();
+
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration as i32;
+            let input = &lighting;
+
+            lighting = (0..width * height)
+                .into_par_iter()
+                .map(|index| {
+                    let (x, y) = ((index % width) as i32, (index / width) as i32);
+                    let center = input[index];
+
+                    let mut sum = Vec3::zero();
+                    let mut weight_sum = 0.0;
+                    for (j, kernel_y) in KERNEL.iter().enumerate() {
+                        for (i, kernel_x) in KERNEL.iter().enumerate() {
+                            let sample_x = x + (i as i32 - 2) * step;
+                            let sample_y = y + (j as i32 - 2) * step;
+                            if sample_x < 0
+                                || sample_y < 0
+                                || sample_x >= width as i32
+                                || sample_y >= height as i32
+                            {
+                                continue;
+                            }
+                            let sample = sample_x as usize + sample_y as usize * width;
+
+                            let mut weight = kernel_x * kernel_y;
+                            weight *= self.color_weight(center, input[sample]);
+                            if let Some(albedo) = albedo {
+                                weight *= self.albedo_weight(albedo[index], albedo[sample]);
+                            }
+                            if let Some(normal) = normal {
+                                weight *= self.normal_weight(normal[index], normal[sample]);
+                            }
+                            if let Some(depth) = depth {
+                                weight *= self.depth_weight(depth[index].x(), depth[sample].x());
+                            }
+
+                            sum += weight * input[sample];
+                            weight_sum += weight;
+                        }
+                    }
+
+                    // the center always has some weight
+                    sum / weight_sum
+                })
+                .collect();
+        }
+
+        lighting
+            .iter()
+            .enumerate()
+            .map(|(index, &lighting)| match albedo {
+                Some(albedo) => lighting * albedo[index].max(Vec3::splat(1e-3)),
+                None => lighting,
+            })
+            .collect()
+    }
+
+    fn color_weight(&self, center: Vec3, sample: Vec3) -> f32 {
+        let (center, sample) = (luminance(center), luminance(sample));
+        let scale = center.max(sample).max(1e-3);
+        (-(center - sample).abs() / (self.sigma_color * scale)).exp()
+    }
+
+    fn albedo_weight(&self, center: Vec3, sample: Vec3) -> f32 {
+        (-(center - sample).length() / self.sigma_albedo).exp()
+    }
+
+    fn normal_weight(&self, center: Vec3, sample: Vec3) -> f32 {
+        // normals are averaged over the pixel so they may not be unit length, and the
+        // background has none at all
+        let lengths = center.length() * sample.length();
+        if lengths < 1e-6 {
+            return 1.0;
+        }
+        (center.dot(sample) / lengths)
+            .max(0.0)
+            .powf(self.sigma_normal)
+    }
+
+    fn depth_weight(&self, center: f32, sample: f32) -> f32 {
+        match (center.is_finite(), sample.is_finite()) {
+            (true, true) => {
+                (-(center - sample).abs() / (self.sigma_depth * center.max(1e-3))).exp()
+            }
+            // both on the background
+            (false, false) => 1.0,
+            _ => 0.0,
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use glam::Vec2;
+
+/// Piecewise constant distribution over [0, 1) built from non-negative weights.
+pub struct Distribution1D {
+    pub func: Vec<f32>,
+    cdf: Vec<f32>,
+    integral: f32,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f32>) -> Distribution1D {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i].max(0.0) / n as f32;
+        }
+
+        let integral = cdf[n];
+        if integral > 0.0 {
+            for c in cdf.iter_mut() {
+                *c /= integral;
+            }
+        } else {
+            // degenerate weights fall back to uniform sampling
+            for (i, c) in cdf.iter_mut().enumerate() {
+                *c = i as f32 / n as f32;
+            }
+        }
+
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    /// Maps `u` to a continuous sample in [0, 1), returning it with its pdf and bucket index.
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        // last cdf entry that is <= u, which skips over empty buckets
+        let (mut lo, mut hi) = (0, self.count() - 1);
+        while lo < hi {
+            let mid = (lo + hi).div_ceil(2);
+            if self.cdf[mid] <= u {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        let index = lo;
+
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let du = if width > 0.0 {
+            (u - self.cdf[index]) / width
+        } else {
+            0.0
+        };
+
+        let x = (index as f32 + du) / self.count() as f32;
+        (x.min(1.0 - f32::EPSILON), self.pdf_at(index), index)
+    }
+
+    pub fn pdf(&self, x: f32) -> f32 {
+        let index = ((x * self.count() as f32) as usize).min(self.count() - 1);
+        self.pdf_at(index)
+    }
+
+    fn pdf_at(&self, index: usize) -> f32 {
+        if self.integral > 0.0 {
+            self.func[index].max(0.0) / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Piecewise constant distribution over [0, 1)², sampled as a marginal over rows
+/// followed by the conditional distribution of the chosen row.
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `func` is laid out row by row, `width` values per row.
+    pub fn new(func: &[f32], width: usize) -> Distribution2D {
+        let conditional: Vec<Distribution1D> = func
+            .chunks(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|d| d.integral()).collect());
+
+        Distribution2D {
+            conditional,
+            marginal,
+        }
+    }
+
+    pub fn sample(&self, u: Vec2) -> (Vec2, f32) {
+        let (y, pdf_y, row) = self.marginal.sample(u.y());
+        let (x, pdf_x, _) = self.conditional[row].sample(u.x());
+        (Vec2::new(x, y), pdf_x * pdf_y)
+    }
+
+    pub fn pdf(&self, p: Vec2) -> f32 {
+        let row = ((p.y() * self.marginal.count() as f32) as usize).min(self.marginal.count() - 1);
+        self.marginal.pdf(p.y()) * self.conditional[row].pdf(p.x())
+    }
+}
@@ -0,0 +1,163 @@
+use crate::distribution::Distribution2D;
+use crate::exr;
+use crate::hit::AsAny;
+use crate::math::luminance;
+use glam::{Vec2, Vec3};
+use image::hdr::HDRDecoder;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+
+/// Radiance arriving from infinitely far away, seen by rays that escape the scene.
+pub trait Environment: Send + Sync + AsAny {
+    fn radiance(&self, dir: Vec3) -> Vec3;
+
+    /// Samples a direction towards the environment, returning it with its solid angle pdf.
+    /// Environments that aren't worth importance sampling return `None` and are only
+    /// reached by scattered rays.
+    fn sample(&self, _u: Vec2) -> Option<(Vec3, f32)> {
+        None
+    }
+
+    fn pdf(&self, _dir: Vec3) -> f32 {
+        0.0
+    }
+}
+
+/// Vertical blend between two colors, the original sky of the tracer.
+pub struct Gradient {
+    pub bottom: Vec3,
+    pub top: Vec3,
+}
+
+impl Default for Gradient {
+    fn default() -> Gradient {
+        Gradient {
+            bottom: Vec3::new(1.0, 1.0, 1.0),
+            top: Vec3::new(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+impl Environment for Gradient {
+    fn radiance(&self, dir: Vec3) -> Vec3 {
+        let t = dir.normalize().y() * 0.5 + 0.5;
+        (1.0 - t) * self.bottom + t * self.top
+    }
+}
+
+pub struct Constant {
+    pub color: Vec3,
+}
+
+impl Environment for Constant {
+    fn radiance(&self, _dir: Vec3) -> Vec3 {
+        self.color
+    }
+}
+
+/// Equirectangular environment map, importance sampled by luminance.
+pub struct EnvironmentMap {
+    pub width: usize,
+    pub height: usize,
+    pub texels: Vec<Vec3>,
+    /// Rotation around the vertical axis, in degrees.
+    pub rotation: f32,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    /// `texels` are laid out row by row, starting from the row looking straight up.
+    pub fn new(width: usize, height: usize, texels: Vec<Vec3>, rotation: f32) -> EnvironmentMap {
+        // rows near the poles cover less solid angle
+        let weights: Vec<f32> = texels
+            .iter()
+            .enumerate()
+            .map(|(i, &texel)| {
+                let theta = ((i / width) as f32 + 0.5) / height as f32 * PI;
+                luminance(texel) * theta.sin()
+            })
+            .collect();
+        let distribution = Distribution2D::new(&weights, width);
+
+        EnvironmentMap {
+            width,
+            height,
+            texels,
+            rotation,
+            distribution,
+        }
+    }
+
+    /// Loads a Radiance `.hdr` or OpenEXR `.exr` map.
+    pub fn load(path: &str, rotation: f32) -> io::Result<EnvironmentMap> {
+        let (width, height, texels) = if path.to_lowercase().ends_with(".exr") {
+            exr::read_rgb(path)?
+        } else {
+            read_hdr(path)?
+        };
+        Ok(EnvironmentMap::new(width, height, texels, rotation))
+    }
+
+    fn dir_to_uv(&self, dir: Vec3) -> Vec2 {
+        let dir = dir.normalize();
+        let phi = dir.x().atan2(-dir.z()) + self.rotation.to_radians();
+        let u = (phi / (2.0 * PI)).rem_euclid(1.0);
+        let v = dir.y().clamp(-1.0, 1.0).acos() / PI;
+        Vec2::new(u, v)
+    }
+
+    fn uv_to_dir(&self, uv: Vec2) -> Vec3 {
+        let phi = uv.x() * 2.0 * PI - self.rotation.to_radians();
+        let theta = uv.y() * PI;
+        Vec3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        )
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, dir: Vec3) -> Vec3 {
+        let uv = self.dir_to_uv(dir);
+        let x = ((uv.x() * self.width as f32) as usize).min(self.width - 1);
+        let y = ((uv.y() * self.height as f32) as usize).min(self.height - 1);
+        self.texels[y * self.width + x]
+    }
+
+    fn sample(&self, u: Vec2) -> Option<(Vec3, f32)> {
+        let (uv, pdf) = self.distribution.sample(u);
+        let sin_theta = (uv.y() * PI).sin();
+        if pdf <= 0.0 || sin_theta <= 0.0 {
+            return None;
+        }
+        // from the image plane to solid angle
+        Some((self.uv_to_dir(uv), pdf / (2.0 * PI * PI * sin_theta)))
+    }
+
+    fn pdf(&self, dir: Vec3) -> f32 {
+        let uv = self.dir_to_uv(dir);
+        let sin_theta = (uv.y() * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(uv) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+fn read_hdr(path: &str) -> io::Result<(usize, usize, Vec<Vec3>)> {
+    let to_io = |e: image::ImageError| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+
+    let decoder = HDRDecoder::new(BufReader::new(File::open(path)?)).map_err(to_io)?;
+    let metadata = decoder.metadata();
+    let texels = decoder
+        .read_image_hdr()
+        .map_err(to_io)?
+        .into_iter()
+        .map(|rgb| Vec3::new(rgb.0[0], rgb.0[1], rgb.0[2]))
+        .collect();
+
+    Ok((metadata.width as usize, metadata.height as usize, texels))
+}
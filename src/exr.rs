@@ -0,0 +1,353 @@
+use glam::f32::Vec3;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+
+const NO_COMPRESSION: u8 = 0;
+const ZIPS_COMPRESSION: u8 = 2;
+const ZIP_COMPRESSION: u8 = 3;
+
+#[derive(Copy, Clone, PartialEq)]
+enum PixelType {
+    Uint,
+    Half,
+    Float,
+}
+
+impl PixelType {
+    fn size(self) -> usize {
+        match self {
+            PixelType::Half => 2,
+            PixelType::Uint | PixelType::Float => 4,
+        }
+    }
+}
+
+struct Channel {
+    name: String,
+    pixel_type: PixelType,
+}
+
+/// Reads the R, G and B channels of a single part scanline OpenEXR image, either
+/// uncompressed or zip compressed. Pixels are returned row by row from the top.
+pub fn read_rgb(path: &str) -> io::Result<(usize, usize, Vec<Vec3>)> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+
+    let mut reader = Reader::new(&bytes);
+    if reader.take(4)? != MAGIC {
+        return Err(invalid("not an OpenEXR file"));
+    }
+
+    // version 2, tiled, long names, deep data and multipart flags
+    let version = reader.u32()?;
+    if version & 0xff != 2 || version & (0x200 | 0x800 | 0x1000) != 0 {
+        return Err(invalid(
+            "only single part scanline OpenEXR images are supported",
+        ));
+    }
+
+    let mut channels = Vec::new();
+    let mut compression = NO_COMPRESSION;
+    let mut data_window = None;
+    loop {
+        let name = reader.string()?;
+        if name.is_empty() {
+            break;
+        }
+        let _type = reader.string()?;
+        let size = reader.u32()? as usize;
+        let value = reader.take(size)?;
+        match name.as_str() {
+            "channels" => channels = read_channels(value)?,
+            "compression" => {
+                compression = *value
+                    .first()
+                    .ok_or_else(|| invalid("empty OpenEXR compression"))?
+            }
+            "dataWindow" => {
+                let mut value = Reader::new(value);
+                data_window = Some([value.i32()?, value.i32()?, value.i32()?, value.i32()?]);
+            }
+            _ => {}
+        }
+    }
+
+    let [x_min, y_min, x_max, y_max] = data_window.ok_or_else(|| invalid("missing data window"))?;
+    if x_max < x_min || y_max < y_min {
+        return Err(invalid("empty OpenEXR data window"));
+    }
+    let width = (i64::from(x_max) - i64::from(x_min) + 1) as usize;
+    let height = (i64::from(y_max) - i64::from(y_min) + 1) as usize;
+
+    let lines_per_chunk = match compression {
+        NO_COMPRESSION | ZIPS_COMPRESSION => 1,
+        ZIP_COMPRESSION => 16,
+        _ => return Err(invalid("unsupported OpenEXR compression")),
+    };
+
+    let channel = |name: &str| {
+        channels
+            .iter()
+            .position(|c| c.name == name)
+            .ok_or_else(|| invalid("OpenEXR image has no R, G and B channels"))
+    };
+    let rgb = [channel("R")?, channel("G")?, channel("B")?];
+
+    let chunk_count = height.div_ceil(lines_per_chunk);
+    if chunk_count > (bytes.len() - reader.pos) / 8 {
+        return Err(invalid("OpenEXR offset table is cut short"));
+    }
+    let mut offsets = Vec::with_capacity(chunk_count);
+    for _ in 0..chunk_count {
+        offsets.push(reader.u64()? as usize);
+    }
+
+    let pixel_size: usize = channels.iter().map(|c| c.pixel_type.size()).sum();
+    let bytes_per_line = pixel_size.checked_mul(width);
+    let image_size = bytes_per_line.and_then(|line| line.checked_mul(height));
+    // zip doesn't shrink data more than about a thousand times, so a window that would
+    // need more is bigger than the file could hold
+    let bytes_per_line = match (bytes_per_line, image_size) {
+        (Some(line), Some(image)) if image / 1032 <= bytes.len() => line,
+        _ => return Err(invalid("OpenEXR data window is larger than the file")),
+    };
+    let mut pixels = vec![Vec3::zero(); width * height];
+    for offset in offsets {
+        let mut chunk = Reader::new(&bytes);
+        chunk.pos = offset;
+        let y = i64::from(chunk.i32()?) - i64::from(y_min);
+        if y < 0 || y >= height as i64 {
+            return Err(invalid("OpenEXR chunk is outside of the data window"));
+        }
+        let y = y as usize;
+        let size = chunk.u32()? as usize;
+        let packed = chunk.take(size)?;
+
+        let lines = lines_per_chunk.min(height - y);
+        let expected = lines * bytes_per_line;
+        let data = if size < expected {
+            unzip(packed, expected)?
+        } else {
+            packed.to_vec()
+        };
+
+        // each line stores every channel in turn, in the order of the channel list
+        let mut pos = 0;
+        for line in 0..lines {
+            let row = &mut pixels[(y + line) * width..(y + line + 1) * width];
+            for (index, c) in channels.iter().enumerate() {
+                let component = rgb.iter().position(|&i| i == index);
+                for pixel in row.iter_mut() {
+                    let value = read_value(&data[pos..], c.pixel_type);
+                    pos += c.pixel_type.size();
+                    if let Some(component) = component {
+                        pixel.as_mut()[component] = value;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok((width, height, pixels))
+}
+
+/// Writes an uncompressed single part scanline image with 32-bit float channels. Every
+/// channel is paired with its values, row by row from the top.
+pub fn write(
+    path: &str,
+    width: usize,
+    height: usize,
+    mut channels: Vec<(String, Vec<f32>)>,
+) -> io::Result<()> {
+    // the channel list has to be sorted by name
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&2u32.to_le_bytes());
+
+    let mut channel_list = Vec::new();
+    for (name, _) in &channels {
+        channel_list.extend_from_slice(name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&2u32.to_le_bytes()); // float
+        channel_list.extend_from_slice(&[0, 0, 0, 0]); // linear flag and reserved
+        channel_list.extend_from_slice(&1u32.to_le_bytes()); // no subsampling
+        channel_list.extend_from_slice(&1u32.to_le_bytes());
+    }
+    channel_list.push(0);
+
+    let window = [0, 0, width as i32 - 1, height as i32 - 1];
+    let window: Vec<u8> = window
+        .iter()
+        .flat_map(|v| v.to_le_bytes().to_vec())
+        .collect();
+
+    let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+        header.extend_from_slice(name.as_bytes());
+        header.push(0);
+        header.extend_from_slice(kind.as_bytes());
+        header.push(0);
+        header.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        header.extend_from_slice(value);
+    };
+    attribute("channels", "chlist", &channel_list);
+    attribute("compression", "compression", &[NO_COMPRESSION]);
+    attribute("dataWindow", "box2i", &window);
+    attribute("displayWindow", "box2i", &window);
+    attribute("lineOrder", "lineOrder", &[0]);
+    attribute("pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute("screenWindowCenter", "v2f", &[0; 8]);
+    attribute("screenWindowWidth", "float", &1f32.to_le_bytes());
+    header.push(0);
+
+    // one scanline per chunk, each with its y coordinate and size
+    let line_size = 4 * width * channels.len();
+    let chunk_size = 8 + line_size;
+    let table_end = header.len() + 8 * height;
+
+    let mut file = File::create(path)?;
+    file.write_all(&header)?;
+    for y in 0..height {
+        file.write_all(&((table_end + y * chunk_size) as u64).to_le_bytes())?;
+    }
+
+    let mut line = Vec::with_capacity(line_size);
+    for y in 0..height {
+        line.clear();
+        line.extend_from_slice(&(y as i32).to_le_bytes());
+        line.extend_from_slice(&(line_size as u32).to_le_bytes());
+        for (_, values) in &channels {
+            for value in &values[y * width..(y + 1) * width] {
+                line.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        file.write_all(&line)?;
+    }
+
+    Ok(())
+}
+
+fn read_channels(value: &[u8]) -> io::Result<Vec<Channel>> {
+    let mut reader = Reader::new(value);
+    let mut channels = Vec::new();
+    loop {
+        let name = reader.string()?;
+        if name.is_empty() {
+            return Ok(channels);
+        }
+        let pixel_type = match reader.u32()? {
+            0 => PixelType::Uint,
+            1 => PixelType::Half,
+            2 => PixelType::Float,
+            _ => return Err(invalid("unknown OpenEXR pixel type")),
+        };
+        // linear flag, reserved bytes and x/y subsampling
+        reader.take(12)?;
+        channels.push(Channel { name, pixel_type });
+    }
+}
+
+fn read_value(data: &[u8], pixel_type: PixelType) -> f32 {
+    match pixel_type {
+        PixelType::Uint => u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as f32,
+        PixelType::Half => half_to_f32(u16::from_le_bytes([data[0], data[1]])),
+        PixelType::Float => f32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+    }
+}
+
+fn unzip(packed: &[u8], expected: usize) -> io::Result<Vec<u8>> {
+    let mut t = inflate::inflate_bytes_zlib(packed).map_err(|e| invalid(&e))?;
+    if t.len() != expected {
+        return Err(invalid("OpenEXR chunk has the wrong size"));
+    }
+
+    // undo the delta predictor, then interleave the two halves back together
+    for i in 1..t.len() {
+        t[i] = t[i - 1].wrapping_add(t[i]).wrapping_sub(128);
+    }
+    let half = t.len().div_ceil(2);
+    let mut data = Vec::with_capacity(t.len());
+    for i in 0..half {
+        data.push(t[i]);
+        if half + i < t.len() {
+            data.push(t[half + i]);
+        }
+    }
+    Ok(data)
+}
+
+fn half_to_f32(h: u16) -> f32 {
+    let sign = u32::from(h >> 15) << 31;
+    let exponent = u32::from((h >> 10) & 0x1f);
+    let mantissa = u32::from(h & 0x3ff);
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        0 => {
+            // subnormal halves are normal floats
+            let mut exponent = 127 - 14;
+            let mut mantissa = mantissa;
+            while mantissa & 0x400 == 0 {
+                mantissa <<= 1;
+                exponent -= 1;
+            }
+            sign | (exponent << 23) | ((mantissa & 0x3ff) << 13)
+        }
+        0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes, pos: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.pos > self.bytes.len() || n > self.bytes.len() - self.pos {
+            return Err(invalid("unexpected end of OpenEXR file"));
+        }
+        let bytes = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from(self.u32()?) | (u64::from(self.u32()?) << 32))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self
+            .bytes
+            .get(self.pos..)
+            .unwrap_or_default()
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| invalid("unterminated OpenEXR string"))?;
+        let string = String::from_utf8_lossy(&self.bytes[self.pos..self.pos + len]).into_owned();
+        self.pos += len + 1;
+        Ok(string)
+    }
+}
@@ -0,0 +1,101 @@
+use crate::exr;
+use crate::trace::RayCount;
+use glam::Vec3;
+use std::io;
+
+/// Extra passes taken from the first hit of every camera ray.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Aov {
+    /// Distance along the view axis, infinite where nothing is hit.
+    Depth,
+    Normal,
+    Position,
+    Albedo,
+    /// Index of the object in the world plus one, zero for the background.
+    ObjectId,
+    /// Shared by materials with the same parameters, zero for the background.
+    MaterialId,
+    /// Light reaching the camera after at most one bounce.
+    Direct,
+    /// Light that bounced off other surfaces before the first hit.
+    Indirect,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 8] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Position,
+        Aov::Albedo,
+        Aov::ObjectId,
+        Aov::MaterialId,
+        Aov::Direct,
+        Aov::Indirect,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Position => "position",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Aov> {
+        Aov::ALL.iter().cloned().find(|aov| aov.name() == name)
+    }
+
+    pub fn channels(self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Albedo | Aov::Direct | Aov::Indirect => &["R", "G", "B"],
+        }
+    }
+}
+
+/// Linear radiance and AOVs of a render, row by row from the bottom of the image.
+pub struct Frame {
+    pub dimensions: (usize, usize),
+    pub color: Vec<Vec3>,
+    pub aovs: Vec<(Aov, Vec<Vec3>)>,
+    pub rays: RayCount,
+}
+
+impl Frame {
+    pub fn aov(&self, aov: Aov) -> Option<&[Vec3]> {
+        self.aovs
+            .iter()
+            .find(|(a, _)| *a == aov)
+            .map(|(_, pixels)| pixels.as_slice())
+    }
+
+    /// Writes the color as the R, G and B channels of an OpenEXR image, with every AOV
+    /// in its own layer.
+    pub fn write_exr(&self, path: &str) -> io::Result<()> {
+        let mut channels = Vec::new();
+        let mut add_layer = |prefix: &str, names: &[&str], pixels: &[Vec3]| {
+            for (component, name) in names.iter().enumerate() {
+                let mut values = Vec::with_capacity(pixels.len());
+                // OpenEXR images go from the top
+                for row in pixels.chunks(self.dimensions.0).rev() {
+                    values.extend(row.iter().map(|p| p.as_ref()[component]));
+                }
+                channels.push((format!("{}{}", prefix, name), values));
+            }
+        };
+
+        add_layer("", &["R", "G", "B"], &self.color);
+        for (aov, pixels) in &self.aovs {
+            add_layer(&format!("{}.", aov.name()), aov.channels(), pixels);
+        }
+
+        exr::write(path, self.dimensions.0, self.dimensions.1, channels)
+    }
+}
@@ -0,0 +1,363 @@
+use crate::camera::{Aperture, Camera};
+use crate::environment::{Constant, Gradient};
+use crate::frame::{Aov, Frame};
+use crate::light::Light;
+use crate::material::Material;
+use crate::scene::Scene;
+use crate::sky::{Sky, SKY_SCALE, SUN_ANGULAR_RADIUS};
+use crate::sphere::Sphere;
+use crate::trace::{RayCount, RenderSettings};
+use glam::{Vec2, Vec3};
+use glium::backend::{Context, Facade};
+use glium::buffer::{Buffer, BufferMode, BufferType};
+use glium::program::ComputeShader;
+use glium::uniform;
+use std::f32::consts::PI;
+use std::fmt::Debug;
+use std::io;
+use std::rc::Rc;
+
+const SOURCE: &str = include_str!("shaders/trace.comp");
+
+/// Pixels along each side of a work group, as the shader declares it.
+const GROUP_SIZE: usize = 8;
+
+/// Path traces in a compute shader that mirrors `trace::render`, for scenes made of
+/// spheres lit by delta lights and a gradient, constant or sky environment. Everything
+/// else is refused with an `InvalidInput` error, for the caller to fall back to the CPU.
+pub struct GpuTracer {
+    context: Rc<Context>,
+    shader: ComputeShader,
+    output: Option<Buffer<[[f32; 4]]>>,
+    frame: u32,
+}
+
+impl GpuTracer {
+    pub fn new<F: Facade>(facade: &F) -> io::Result<GpuTracer> {
+        let context = facade.get_context().clone();
+        if !ComputeShader::is_supported(&context) {
+            return Err(io::Error::other("compute shaders need OpenGL 4.3"));
+        }
+
+        validate(SOURCE)?;
+        let shader = ComputeShader::from_source(&context, SOURCE).map_err(gl_error)?;
+
+        Ok(GpuTracer {
+            context,
+            shader,
+            output: None,
+            frame: 0,
+        })
+    }
+
+    /// Same as `trace::render`, only the albedo, normal and depth AOVs are supported.
+    /// Seeded renders repeat, though with other random numbers than on the CPU, and
+    /// unseeded ones get new numbers every call.
+    pub fn render(
+        &mut self,
+        camera: &Camera,
+        scene: &Scene,
+        settings: &RenderSettings,
+    ) -> io::Result<Frame> {
+        if let Some(aov) = settings
+            .aovs
+            .iter()
+            .find(|aov| ![Aov::Albedo, Aov::Normal, Aov::Depth].contains(aov))
+        {
+            return Err(unsupported(&format!("the {} AOV", aov.name())));
+        }
+        let film = Film::new(camera)?;
+        let packed = PackedScene::new(scene)?;
+
+        let (width, height) = settings.dimensions;
+        let pixels = width * height;
+        let output = match self.output.take() {
+            Some(output) if output.len() == 3 * pixels => output,
+            _ => Buffer::empty_array(
+                &self.context,
+                BufferType::ShaderStorageBuffer,
+                3 * pixels,
+                BufferMode::Dynamic,
+            )
+            .map_err(gl_error)?,
+        };
+
+        let storage = |data: &[[f32; 4]]| {
+            Buffer::new(
+                &self.context,
+                data,
+                BufferType::ShaderStorageBuffer,
+                BufferMode::Default,
+            )
+            .map_err(gl_error)
+        };
+        let spheres = storage(&packed.spheres)?;
+        let lights = storage(&packed.lights)?;
+        let environment = storage(&packed.environment)?;
+        let rays = Buffer::new(
+            &self.context,
+            &[0u32; 3][..],
+            BufferType::ShaderStorageBuffer,
+            BufferMode::Default,
+        )
+        .map_err(gl_error)?;
+
+        let vec3 = |v: Vec3| [v.x(), v.y(), v.z()];
+        self.frame = self.frame.wrapping_add(1);
+        let rng_seed = settings
+            .seed
+            .map_or(self.frame, |seed| (seed ^ (seed >> 32)) as u32);
+        self.shader.execute(
+            uniform! {
+                Spheres: &spheres,
+                Lights: &lights,
+                Environment: &environment,
+                Output: &output,
+                Rays: &rays,
+                dimensions: [width as i32, height as i32],
+                samples: settings.samples,
+                depth: settings.depth,
+                seed: rng_seed,
+                sphere_count: packed.sphere_count as i32,
+                light_count: packed.light_count as i32,
+                camera_origin: vec3(camera.origin),
+                camera_u: vec3(camera.u),
+                camera_v: vec3(camera.v),
+                camera_w: vec3(camera.w),
+                film_corner: vec3(film.corner),
+                film_horizontal: vec3(film.horizontal),
+                film_vertical: vec3(film.vertical),
+                lens_radius: camera.lens_radius,
+                focus_dist: camera.focus_dist,
+                roulette: settings.roulette.unwrap_or(-1),
+            },
+            width.div_ceil(GROUP_SIZE) as u32,
+            height.div_ceil(GROUP_SIZE) as u32,
+            1,
+        );
+
+        let values = output.read().map_err(gl_error)?;
+        let counts = rays.read().map_err(gl_error)?;
+        self.output = Some(output);
+
+        let texel = |pixel: usize, offset: usize| {
+            let [x, y, z, _] = values[3 * pixel + offset];
+            Vec3::new(x, y, z)
+        };
+        let mut frame = Frame {
+            dimensions: settings.dimensions,
+            color: (0..pixels).map(|pixel| texel(pixel, 0)).collect(),
+            aovs: Vec::new(),
+            rays: RayCount {
+                camera: u64::from(counts[0]),
+                bounce: u64::from(counts[1]),
+                shadow: u64::from(counts[2]),
+                // the shader tests every sphere, there's no tree to walk
+                node_visits: 0,
+            },
+        };
+        for &aov in &settings.aovs {
+            let buffer = (0..pixels)
+                .map(|pixel| match aov {
+                    Aov::Albedo => texel(pixel, 1),
+                    Aov::Normal => texel(pixel, 2),
+                    _ => Vec3::splat(values[3 * pixel + 2][3]),
+                })
+                .collect();
+            frame.aovs.push((aov, buffer));
+        }
+        Ok(frame)
+    }
+}
+
+/// Checks the shader with shaderc, which reports errors the same way whatever the driver.
+/// glium can't load the SPIR-V it makes, so the driver still compiles the GLSL itself.
+fn validate(source: &str) -> io::Result<()> {
+    let failed = |message: &str| io::Error::other(message.to_string());
+    let mut compiler = shaderc::Compiler::new().ok_or_else(|| failed("couldn't start shaderc"))?;
+    let mut options =
+        shaderc::CompileOptions::new().ok_or_else(|| failed("couldn't start shaderc"))?;
+    options.set_target_env(shaderc::TargetEnv::OpenGL, 0);
+    compiler
+        .compile_into_spirv(
+            source,
+            shaderc::ShaderKind::Compute,
+            "trace.comp",
+            "main",
+            Some(&options),
+        )
+        .map(|_| ())
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))
+}
+
+fn gl_error<E: Debug>(error: E) -> io::Error {
+    io::Error::other(format!("{:?}", error))
+}
+
+fn unsupported(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("the GPU tracer doesn't support {}", what),
+    )
+}
+
+/// Pinhole directions of a camera whose model maps the image linearly onto a plane in
+/// front of it, like `Perspective`.
+pub struct Film {
+    /// Direction at uv = (0, 0), in the camera's local frame.
+    pub corner: Vec3,
+    pub horizontal: Vec3,
+    pub vertical: Vec3,
+}
+
+impl Film {
+    pub fn new(camera: &Camera) -> io::Result<Film> {
+        match camera.aperture {
+            Aperture::Circle => {}
+            _ => return Err(unsupported("aperture shapes")),
+        }
+        if camera.anamorphic_squeeze != 1.0 || camera.cat_eye != 0.0 {
+            return Err(unsupported("anamorphic or cat's eye bokeh"));
+        }
+        if camera.focus_tilt != Vec2::zero() {
+            return Err(unsupported("tilted focus"));
+        }
+
+        let ray = |u: f32, v: f32| camera.model.local_ray(Vec2::new(u, v), camera.aspect);
+        let (corner, right, top) = (ray(0.0, 0.0), ray(1.0, 0.0), ray(0.0, 1.0));
+        let film = Film {
+            corner: corner.dir,
+            horizontal: right.dir - corner.dir,
+            vertical: top.dir - corner.dir,
+        };
+
+        // rays from the pinhole through a plane are the only ones the shader can make
+        for &(u, v) in &[(0.5, 0.5), (1.0, 1.0), (0.25, 0.75)] {
+            let expected = ray(u, v);
+            let dir = film.corner + u * film.horizontal + v * film.vertical;
+            let error = (dir - expected.dir).length() / expected.dir.length();
+            if expected.origin != Vec3::zero() || error > 1e-4 || error.is_nan() {
+                return Err(unsupported("this camera model"));
+            }
+        }
+        Ok(film)
+    }
+}
+
+/// A scene flattened into the arrays of vec4 the shader reads. Every array has at least
+/// one element, since buffers can't be empty.
+pub struct PackedScene {
+    /// Center and radius, motion and material, albedo and fuzz or refractive index.
+    pub spheres: Vec<[f32; 4]>,
+    pub sphere_count: usize,
+    /// Position and kind, direction and cosine of the inner angle, intensity and cosine of
+    /// the outer angle.
+    pub lights: Vec<[f32; 4]>,
+    pub light_count: usize,
+    /// Kind, then the bottom and top colors of a gradient, the color of a constant, or the
+    /// parameters of a sky: cosine of the sun radius, theta of the sun and luminance scale
+    /// after the kind, then the sun direction, sun radiance, 15 Perez coefficients and the
+    /// zenith values.
+    pub environment: Vec<[f32; 4]>,
+}
+
+impl PackedScene {
+    pub fn new(scene: &Scene) -> io::Result<PackedScene> {
+        let vec4 = |v: Vec3, w: f32| [v.x(), v.y(), v.z(), w];
+
+        let mut spheres = Vec::new();
+        for object in &scene.world {
+            let sphere: &Sphere = object
+                .as_ref()
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| unsupported("objects other than spheres"))?;
+            let (kind, param) = match sphere.mat {
+                Material::Lambert { .. } => (0.0, 0.0),
+                Material::Metal { fuzz, .. } => (1.0, fuzz),
+                Material::Dielectric { ref_idx, .. } => (2.0, ref_idx),
+                Material::Isotropic { .. } => (3.0, 0.0),
+            };
+            spheres.push(vec4(sphere.center, sphere.radius));
+            spheres.push(vec4(sphere.motion, kind));
+            spheres.push(vec4(sphere.mat.albedo(), param));
+        }
+
+        let mut lights = Vec::new();
+        for light in &scene.lights {
+            match *light {
+                Light::Point {
+                    position,
+                    intensity,
+                } => {
+                    lights.push(vec4(position, 0.0));
+                    lights.push([0.0; 4]);
+                    lights.push(vec4(intensity, 0.0));
+                }
+                Light::Spot {
+                    position,
+                    direction,
+                    intensity,
+                    inner_angle,
+                    outer_angle,
+                } => {
+                    lights.push(vec4(position, 1.0));
+                    lights.push(vec4(direction, inner_angle.to_radians().cos()));
+                    lights.push(vec4(intensity, outer_angle.to_radians().cos()));
+                }
+                Light::Directional {
+                    direction,
+                    irradiance,
+                } => {
+                    lights.push([0.0, 0.0, 0.0, 2.0]);
+                    lights.push(vec4(direction, 0.0));
+                    lights.push(vec4(irradiance, 0.0));
+                }
+            }
+        }
+
+        let environment = scene.environment.as_ref().as_any();
+        let environment = if let Some(gradient) = environment.downcast_ref::<Gradient>() {
+            vec![
+                [0.0; 4],
+                vec4(gradient.bottom, 0.0),
+                vec4(gradient.top, 0.0),
+            ]
+        } else if let Some(constant) = environment.downcast_ref::<Constant>() {
+            vec![[1.0, 0.0, 0.0, 0.0], vec4(constant.color, 0.0)]
+        } else if let Some(sky) = environment.downcast_ref::<Sky>() {
+            let theta_s = (PI / 2.0 - sky.sun_elevation().to_radians()).clamp(0.0, PI / 2.0 - 0.01);
+            let mut packed = vec![
+                [2.0, SUN_ANGULAR_RADIUS.cos(), theta_s, SKY_SCALE],
+                vec4(sky.sun_dir(), 0.0),
+                vec4(sky.sun_radiance(), 0.0),
+            ];
+            let coefficients: Vec<f32> = sky.perez().iter().flatten().cloned().collect();
+            for chunk in coefficients.chunks(4) {
+                let mut values = [0.0; 4];
+                values[..chunk.len()].copy_from_slice(chunk);
+                packed.push(values);
+            }
+            let zenith = sky.zenith();
+            packed.push([zenith[0], zenith[1], zenith[2], 0.0]);
+            packed
+        } else {
+            return Err(unsupported("this environment"));
+        };
+
+        let (sphere_count, light_count) = (scene.world.len(), scene.lights.len());
+        if spheres.is_empty() {
+            spheres.push([0.0; 4]);
+        }
+        if lights.is_empty() {
+            lights.push([0.0; 4]);
+        }
+        Ok(PackedScene {
+            spheres,
+            sphere_count,
+            lights,
+            light_count,
+            environment,
+        })
+    }
+}
@@ -1,14 +1,78 @@
 use crate::material::Material;
 use crate::ray::Ray;
 use glam::f32::Vec3;
+use std::any::Any;
 
 pub struct Hit {
     pub t: f32,
     pub pos: Vec3,
     pub normal: Vec3,
     pub mat: Material,
+    /// Index of the hit object in the list that contains it.
+    pub object: usize,
 }
 
-pub trait Hittable {
+/// Gets concrete objects back out of a world of trait objects, for editing.
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+pub trait Hittable: Send + Sync + AsAny {
     fn hit(&self, r: &Ray, range: [f32; 2]) -> Option<Hit>;
+
+    /// Fraction of the light travelling along `r` inside `range` that makes it through.
+    fn transmittance(&self, r: &Ray, range: [f32; 2]) -> f32 {
+        if self.hit(r, range).is_some() {
+            0.0
+        } else {
+            1.0
+        }
+    }
+}
+
+impl<T: Hittable + ?Sized + 'static> Hittable for Box<T> {
+    fn hit(&self, r: &Ray, range: [f32; 2]) -> Option<Hit> {
+        (**self).hit(r, range)
+    }
+
+    fn transmittance(&self, r: &Ray, range: [f32; 2]) -> f32 {
+        (**self).transmittance(r, range)
+    }
+}
+
+impl<T: Hittable + 'static> Hittable for Vec<T> {
+    fn hit(&self, r: &Ray, range: [f32; 2]) -> Option<Hit> {
+        let mut closest_hit = None;
+        let mut range = range;
+        for (index, object) in self.iter().enumerate() {
+            if let Some(mut hit) = object.hit(r, range) {
+                range[1] = hit.t;
+                hit.object = index;
+                closest_hit = Some(hit);
+            }
+        }
+        closest_hit
+    }
+
+    fn transmittance(&self, r: &Ray, range: [f32; 2]) -> f32 {
+        let mut transmittance = 1.0;
+        for object in self {
+            transmittance *= object.transmittance(r, range);
+            if transmittance <= 0.0 {
+                break;
+            }
+        }
+        transmittance
+    }
 }
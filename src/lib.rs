@@ -1,7 +1,28 @@
+pub mod aabb;
+pub mod animation;
+pub mod bvh;
 pub mod camera;
+pub mod camera_path;
+pub mod denoise;
+pub mod distribution;
+pub mod environment;
+pub mod exr;
+pub mod frame;
+pub mod gpu;
 pub mod hit;
+pub mod light;
 pub mod material;
 pub mod math;
+pub mod overlay;
 pub mod ray;
+pub mod rng;
+pub mod scene;
+pub mod scene_file;
+pub mod simd;
+pub mod sky;
 pub mod sphere;
+pub mod temporal;
 pub mod trace;
+pub mod viewer;
+pub mod volume;
+pub mod wavefront;
@@ -0,0 +1,104 @@
+use glam::f32::Vec3;
+
+/// Lights with no area, they can't be hit by rays and only contribute
+/// through shadow rays cast from surfaces.
+#[derive(Copy, Clone)]
+pub enum Light {
+    Point {
+        position: Vec3,
+        intensity: Vec3,
+    },
+    /// Full intensity inside `inner_angle`, fading out to nothing at `outer_angle`.
+    /// Angles are measured from the axis, in degrees.
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        intensity: Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+    /// Parallel light travelling along `direction`, like the sun.
+    Directional {
+        direction: Vec3,
+        irradiance: Vec3,
+    },
+}
+
+impl Light {
+    /// The same light with another intensity, or irradiance for directional lights.
+    pub fn with_intensity(self, intensity: Vec3) -> Light {
+        match self {
+            Light::Point { position, .. } => Light::Point {
+                position,
+                intensity,
+            },
+            Light::Spot {
+                position,
+                direction,
+                inner_angle,
+                outer_angle,
+                ..
+            } => Light::Spot {
+                position,
+                direction,
+                intensity,
+                inner_angle,
+                outer_angle,
+            },
+            Light::Directional { direction, .. } => Light::Directional {
+                direction,
+                irradiance: intensity,
+            },
+        }
+    }
+
+    /// Direction from `pos` towards the light, the distance to it and the radiance
+    /// arriving at `pos` if nothing is in the way.
+    pub fn sample(&self, pos: Vec3) -> Option<(Vec3, f32, Vec3)> {
+        match *self {
+            Light::Point {
+                position,
+                intensity,
+            } => {
+                let to_light = position - pos;
+                let distance_squared = to_light.length_squared();
+                let distance = distance_squared.sqrt();
+                Some((to_light / distance, distance, intensity / distance_squared))
+            }
+
+            Light::Spot {
+                position,
+                direction,
+                intensity,
+                inner_angle,
+                outer_angle,
+            } => {
+                let to_light = position - pos;
+                let distance_squared = to_light.length_squared();
+                let distance = distance_squared.sqrt();
+                let dir = to_light / distance;
+
+                let cos = Vec3::dot(-dir, direction.normalize());
+                let cos_inner = inner_angle.to_radians().cos();
+                let cos_outer = outer_angle.to_radians().cos();
+                if cos <= cos_outer {
+                    return None;
+                }
+                // a cone with no room between the angles has a hard edge at the outer one
+                let t = if cos >= cos_inner {
+                    1.0
+                } else {
+                    (cos - cos_outer) / (cos_inner - cos_outer)
+                };
+                let falloff = t * t * (3.0 - 2.0 * t);
+
+                Some((dir, distance, intensity * falloff / distance_squared))
+            }
+
+            Light::Directional {
+                direction,
+                irradiance,
+            } => Some((-direction.normalize(), f32::MAX, irradiance)),
+        }
+    }
+}
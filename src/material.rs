@@ -1,27 +1,33 @@
 use crate::hit::Hit;
-use crate::math::{random_in_unit_sphere, reflect, refract, schlick};
+use crate::math::{random_in_unit_sphere, random_unit_vector, reflect, refract, schlick};
 use crate::ray::Ray;
+use crate::rng;
 use glam::f32::Vec3;
+use std::f32::consts::PI;
 
 #[derive(Copy, Clone)]
 pub enum Material {
     Lambert { albedo: Vec3 },
     Metal { albedo: Vec3, fuzz: f32 },
     Dielectric { albedo: Vec3, ref_idx: f32 },
+    Isotropic { albedo: Vec3 },
 }
 
 impl Material {
     pub fn scatter(self, r: &Ray, hit: &Hit) -> (Vec3, Option<Ray>) {
         match self {
             Material::Lambert { albedo } => {
-                let target = hit.pos + hit.normal + random_in_unit_sphere();
-                (albedo, Some(Ray::new(hit.pos, target - hit.pos)))
+                let target = hit.pos + hit.normal + random_unit_vector();
+                (
+                    albedo,
+                    Some(Ray::at_time(hit.pos, target - hit.pos, r.time)),
+                )
             }
 
             Material::Metal { albedo, fuzz } => {
                 let reflected_dir = reflect(r.dir, hit.normal) + fuzz * random_in_unit_sphere();
                 if Vec3::dot(reflected_dir, hit.normal) > 0.0 {
-                    (albedo, Some(Ray::new(hit.pos, reflected_dir)))
+                    (albedo, Some(Ray::at_time(hit.pos, reflected_dir, r.time)))
                 } else {
                     (albedo, None)
                 }
@@ -33,7 +39,12 @@ impl Material {
                 if Vec3::dot(r.dir, hit.normal) > 0.0 {
                     outward_normal = -hit.normal;
                     ni_over_nt = ref_idx;
-                    cos = ref_idx * Vec3::dot(r.dir, hit.normal) / r.dir.length();
+                    // reflectance is the same both ways through the surface, so leaving
+                    // the glass it is taken at the angle outside
+                    let cos_inside = Vec3::dot(r.dir, hit.normal) / r.dir.length();
+                    cos = (1.0 - ref_idx * ref_idx * (1.0 - cos_inside * cos_inside))
+                        .max(0.0)
+                        .sqrt();
                 } else {
                     outward_normal = hit.normal;
                     ni_over_nt = 1.0 / ref_idx;
@@ -42,13 +53,80 @@ impl Material {
 
                 if let Some(refract_dir) = refract(&r.dir, &outward_normal, ni_over_nt) {
                     let reflect_prob = schlick(cos, ref_idx);
-                    if rand::random::<f32>() >= reflect_prob {
-                        return (albedo, Some(Ray::new(hit.pos, refract_dir)));
+                    if rng::random::<f32>() >= reflect_prob {
+                        return (albedo, Some(Ray::at_time(hit.pos, refract_dir, r.time)));
                     }
                 }
 
-                (albedo, Some(Ray::new(hit.pos, reflect(r.dir, hit.normal))))
+                (
+                    albedo,
+                    Some(Ray::at_time(hit.pos, reflect(r.dir, hit.normal), r.time)),
+                )
             }
+
+            Material::Isotropic { albedo } => (
+                albedo,
+                Some(Ray::at_time(hit.pos, random_unit_vector(), r.time)),
+            ),
+        }
+    }
+
+    /// BSDF times cosine towards `dir`, along with the pdf `scatter` has of sampling it.
+    /// Specular materials return `None` since they can't be lit by sampling lights.
+    pub fn eval(self, hit: &Hit, dir: Vec3) -> Option<(Vec3, f32)> {
+        match self {
+            Material::Lambert { albedo } => {
+                let cos = Vec3::dot(hit.normal, dir.normalize()).max(0.0);
+                Some((albedo * cos / PI, cos / PI))
+            }
+            Material::Isotropic { albedo } => Some((albedo / (4.0 * PI), 1.0 / (4.0 * PI))),
+            Material::Metal { .. } | Material::Dielectric { .. } => None,
+        }
+    }
+
+    pub fn albedo(self) -> Vec3 {
+        match self {
+            Material::Lambert { albedo }
+            | Material::Metal { albedo, .. }
+            | Material::Dielectric { albedo, .. }
+            | Material::Isotropic { albedo } => albedo,
+        }
+    }
+
+    /// The same material with another albedo.
+    pub fn with_albedo(self, albedo: Vec3) -> Material {
+        match self {
+            Material::Lambert { .. } => Material::Lambert { albedo },
+            Material::Metal { fuzz, .. } => Material::Metal { albedo, fuzz },
+            Material::Dielectric { ref_idx, .. } => Material::Dielectric { albedo, ref_idx },
+            Material::Isotropic { .. } => Material::Isotropic { albedo },
+        }
+    }
+
+    /// Identifier shared by materials with the same parameters, never zero and small
+    /// enough to be stored exactly in a f32. It's a 24-bit FNV-1a hash of the parameters,
+    /// the same from one build to the next, though different materials may share one.
+    pub fn id(self) -> u32 {
+        let (kind, param) = match self {
+            Material::Lambert { .. } => (0, 0.0),
+            Material::Metal { fuzz, .. } => (1, fuzz),
+            Material::Dielectric { ref_idx, .. } => (2, ref_idx),
+            Material::Isotropic { .. } => (3, 0.0),
+        };
+        let albedo = self.albedo();
+
+        let words = [
+            kind,
+            albedo.x().to_bits(),
+            albedo.y().to_bits(),
+            albedo.z().to_bits(),
+            param.to_bits(),
+        ];
+        let mut hash: u32 = 0x811c_9dc5;
+        for byte in words.iter().flat_map(|word| word.to_le_bytes().to_vec()) {
+            hash = (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193);
         }
+        // xor folded down to 24 bits, leaving out zero
+        ((hash >> 24) ^ (hash & 0x00ff_ffff)).max(1)
     }
 }
@@ -1,17 +1,27 @@
+use crate::rng;
 use glam::f32::Vec3;
-use glam::Vec2;
-use rand::distributions::{Distribution, UnitCircle, UnitSphereSurface};
+use glam::{Quat, Vec2, Vec4};
+use rand::distributions::UnitSphereSurface;
+use std::f32::consts::PI;
 
-pub fn random_in_unit_sphere() -> Vec3 {
-    let unit_sphere = UnitSphereSurface::new();
-    let sample = unit_sphere.sample(&mut rand::thread_rng());
+/// Uniform direction, a point on the surface of the unit sphere.
+pub fn random_unit_vector() -> Vec3 {
+    let sample = rng::sample(UnitSphereSurface::new());
     Vec3::new(sample[0] as f32, sample[1] as f32, sample[2] as f32)
 }
 
+/// Uniform point inside the unit ball.
+pub fn random_in_unit_sphere() -> Vec3 {
+    // the volume within radius r grows as r³
+    random_unit_vector() * rng::random::<f32>().cbrt()
+}
+
+/// Uniform point inside the unit disk.
 pub fn random_in_unit_disk() -> Vec2 {
-    let unit_disk = UnitCircle::new();
-    let sample = unit_disk.sample(&mut rand::thread_rng());
-    Vec2::new(sample[0] as f32, sample[1] as f32)
+    // the area within radius r grows as r²
+    let r = rng::random::<f32>().sqrt();
+    let phi = 2.0 * PI * rng::random::<f32>();
+    Vec2::new(r * phi.cos(), r * phi.sin())
 }
 
 pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
@@ -35,3 +45,35 @@ pub fn schlick(cos: f32, ref_idx: f32) -> f32 {
     let r0 = r0_sqrt * r0_sqrt;
     return r0 + (1.0 - r0) * (1.0 - cos).powi(5);
 }
+
+pub fn luminance(color: Vec3) -> f32 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
+
+/// Two unit vectors completing `n` to an orthonormal basis.
+pub fn orthonormal_basis(n: Vec3) -> (Vec3, Vec3) {
+    let helper = if n.x().abs() > 0.9 {
+        Vec3::unit_y()
+    } else {
+        Vec3::unit_x()
+    };
+    let t = Vec3::cross(helper, n).normalize();
+    (t, Vec3::cross(n, t))
+}
+
+/// Interpolates between two rotations at constant angular speed, along the shorter arc.
+pub fn slerp(a: Quat, b: Quat, t: f32) -> Quat {
+    let (a, mut b) = (Vec4::from(a), Vec4::from(b));
+    let mut cos = a.dot(b);
+    if cos < 0.0 {
+        b = -b;
+        cos = -cos;
+    }
+
+    // nearly the same rotation, where the angle can't be trusted
+    if cos > 0.9995 {
+        return Quat::from((a + t * (b - a)).normalize());
+    }
+    let theta = cos.acos();
+    Quat::from((a * ((1.0 - t) * theta).sin() + b * (t * theta).sin()) / theta.sin())
+}
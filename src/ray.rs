@@ -3,11 +3,17 @@ use glam::f32::Vec3;
 pub struct Ray {
     pub origin: Vec3,
     pub dir: Vec3,
+    /// When the ray was cast, from 0 when the shutter opens to 1 when it closes.
+    pub time: f32,
 }
 
 impl Ray {
     pub fn new(origin: Vec3, dir: Vec3) -> Ray {
-        Ray { origin, dir }
+        Ray::at_time(origin, dir, 0.0)
+    }
+
+    pub fn at_time(origin: Vec3, dir: Vec3, time: f32) -> Ray {
+        Ray { origin, dir, time }
     }
 
     pub fn point_at(&self, t: f32) -> Vec3 {
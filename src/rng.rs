@@ -0,0 +1,38 @@
+use rand::distributions::{Distribution, Standard};
+use rand::rngs::SmallRng;
+use rand::{FromEntropy, Rng, SeedableRng};
+use std::cell::RefCell;
+
+thread_local! {
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy());
+}
+
+/// A random value from this thread's generator, what the renderer uses in place of
+/// `rand::random` so that renders can be seeded.
+pub fn random<T>() -> T
+where
+    Standard: Distribution<T>,
+{
+    RNG.with(|rng| rng.borrow_mut().gen())
+}
+
+/// A sample of `distribution` from this thread's generator.
+pub fn sample<T>(distribution: impl Distribution<T>) -> T {
+    RNG.with(|rng| distribution.sample(&mut *rng.borrow_mut()))
+}
+
+/// Restarts this thread's generator from `keys`, the seed of a render followed by
+/// whatever identifies the work about to be done, like a pixel. The same work then gets
+/// the same numbers whichever thread it runs on.
+pub fn seed(keys: &[u64]) {
+    let state = keys.iter().fold(0, |state, &key| mix(state ^ key));
+    RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(state));
+}
+
+/// The splitmix64 finalizer, so that nearby keys give unrelated seeds.
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
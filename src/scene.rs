@@ -0,0 +1,140 @@
+use crate::aabb::Aabb;
+use crate::animation::Animation;
+use crate::camera::Camera;
+use crate::environment::{Constant, Environment, Gradient};
+use crate::hit::Hittable;
+use crate::light::Light;
+use crate::material::Material;
+use crate::sphere::Sphere;
+use crate::volume::{Volume, VoxelGrid};
+use glam::Vec3;
+
+pub type World = Vec<Box<dyn Hittable>>;
+
+pub struct Scene {
+    pub world: World,
+    pub environment: Box<dyn Environment>,
+    pub lights: Vec<Light>,
+    pub animation: Animation,
+}
+
+impl Scene {
+    pub fn new(world: World) -> Scene {
+        Scene {
+            world,
+            environment: Box::new(Gradient::default()),
+            lights: Vec::new(),
+            animation: Animation::default(),
+        }
+    }
+
+    /// Moves the scene and `camera` to `time` in seconds, with the shutter open for
+    /// `shutter` seconds from there.
+    pub fn set_time(&mut self, camera: &mut Camera, time: f32, shutter: f32) {
+        let animation = std::mem::take(&mut self.animation);
+        animation.apply(self, camera, time, shutter);
+        self.animation = animation;
+    }
+
+    /// Removes the object at `index` from the world along with its animation, keeping the
+    /// animation of the objects after it.
+    pub fn remove(&mut self, index: usize) -> Box<dyn Hittable> {
+        self.animation.remove_object(index);
+        self.world.remove(index)
+    }
+
+    /// The object at `index` in the world, if it is a sphere.
+    pub fn sphere(&self, index: usize) -> Option<&Sphere> {
+        // through the box, or we'd be asking whether the box is a sphere
+        self.world.get(index)?.as_ref().as_any().downcast_ref()
+    }
+
+    pub fn sphere_mut(&mut self, index: usize) -> Option<&mut Sphere> {
+        self.world
+            .get_mut(index)?
+            .as_mut()
+            .as_any_mut()
+            .downcast_mut()
+    }
+}
+
+pub fn default_world() -> World {
+    vec![
+        Box::new(Sphere::new(
+            Vec3::new(0.0, 0.0, -1.0),
+            0.5,
+            Material::Lambert {
+                albedo: Vec3::new(0.1, 0.2, 0.5),
+            },
+        )),
+        Box::new(Sphere::new(
+            Vec3::new(0.0, -100.5, -1.0),
+            100.0,
+            Material::Lambert {
+                albedo: Vec3::new(0.6, 0.6, 0.4),
+            },
+        )),
+        Box::new(Sphere::new(
+            Vec3::new(1.0, 0.0, -1.0),
+            0.5,
+            Material::Metal {
+                albedo: Vec3::new(0.8, 0.6, 0.2),
+                fuzz: 0.3,
+            },
+        )),
+        Box::new(Sphere::new(
+            Vec3::new(-1.0, 0.0, -1.0),
+            0.5,
+            Material::Dielectric {
+                albedo: Vec3::new(0.9, 0.8, 0.8),
+                ref_idx: 1.5,
+            },
+        )),
+        Box::new(Sphere::new(
+            Vec3::new(-1.0, 0.0, -1.0),
+            -0.45,
+            Material::Dielectric {
+                albedo: Vec3::one(),
+                ref_idx: 1.5,
+            },
+        )),
+    ]
+}
+
+/// The default spheres with a procedural cloud floating above them.
+pub fn cloud_world() -> World {
+    let mut world = default_world();
+    world.push(Box::new(Volume::new(
+        Aabb::new(Vec3::new(-1.5, 0.4, -2.0), Vec3::new(1.5, 1.4, -0.5)),
+        VoxelGrid::from_noise([96, 32, 48], 6.0, 4, 7),
+        40.0,
+        Vec3::splat(0.9),
+    )));
+    world
+}
+
+/// The default spheres at dusk, lit by one light of each kind.
+pub fn lights_scene() -> Scene {
+    let mut scene = Scene::new(default_world());
+    scene.environment = Box::new(Constant {
+        color: Vec3::new(0.02, 0.02, 0.04),
+    });
+    scene.lights = vec![
+        Light::Point {
+            position: Vec3::new(0.0, 1.0, -0.3),
+            intensity: Vec3::new(1.5, 1.2, 0.8),
+        },
+        Light::Spot {
+            position: Vec3::new(-2.0, 2.0, 0.0),
+            direction: Vec3::new(1.0, -2.0, -1.0),
+            intensity: Vec3::new(5.0, 5.0, 6.0),
+            inner_angle: 15.0,
+            outer_angle: 25.0,
+        },
+        Light::Directional {
+            direction: Vec3::new(-1.0, -1.0, -0.5),
+            irradiance: Vec3::new(0.8, 0.6, 0.4),
+        },
+    ];
+    scene
+}
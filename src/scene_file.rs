@@ -0,0 +1,504 @@
+use crate::aabb::Aabb;
+use crate::animation::{Interpolation, Key, Parameter, Track};
+use crate::camera::{
+    Aperture, ApertureMask, Camera, Equirectangular, Fisheye, Orthographic, PhysicalLens,
+    StereoEquirectangular,
+};
+use crate::environment::{Constant, EnvironmentMap, Gradient};
+use crate::light::Light;
+use crate::material::Material;
+use crate::scene::Scene;
+use crate::sky::Sky;
+use crate::sphere::Sphere;
+use crate::volume::{Volume, VoxelGrid};
+use glam::{deg, Quat, Vec2, Vec3};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Loads a scene description. Every line is a keyword followed by `key=value` parameters,
+/// vectors are written as comma separated components and `#` starts a comment:
+///
+/// ```text
+/// camera model=perspective origin=0,0.25,0 lookat=0,0,-1 fov=100 aperture=0.025 focus=1
+/// camera origin=0,0.25,0 rotation=0,-10,0 focal_length=35 f_stop=2.8 autofocus=1
+/// camera aperture=0.1 aperture_shape=polygon blades=6 squeeze=2 cat_eye=0.5 tilt_x=10
+/// sphere center=0,0,-1 radius=0.5 material=lambert albedo=0.1,0.2,0.5
+/// volume min=-1,0,-2 max=1,1,-1 grid=noise resolution=64,32,32 density=40 albedo=0.9,0.9,0.9
+/// environment type=sky elevation=45 azimuth=0 turbidity=3
+/// light type=point position=0,1,0 intensity=1,1,1
+/// animate parameter=center object=0 time=2 value=0,1,-1 interpolation=bezier
+/// ```
+///
+/// Every `animate` line adds a key to a parameter of an object or light, given by its
+/// index in the order they appear in the file, or of the camera. Relative paths are
+/// resolved from the directory of the scene file. The camera aspect is left at 1 for the
+/// caller to match with its resolution.
+///
+/// `autofocus=1` focuses the camera on the center of the view, and again whenever it moves
+/// in the viewer or along a camera path. Without it the camera keeps its `focus`.
+pub fn load(path: &str) -> io::Result<(Scene, Camera)> {
+    let source = fs::read_to_string(path)?;
+    let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+
+    let mut scene = Scene::new(Vec::new());
+    let mut camera = None;
+    let mut autofocus = false;
+
+    for (index, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let params = Params::parse(index + 1, line)?;
+        match params.keyword {
+            "camera" => {
+                camera = Some(params.camera(directory)?);
+                autofocus = params.f32("autofocus", Some(0.0))? != 0.0;
+            }
+            "sphere" => scene.world.push(Box::new(Sphere::new(
+                params.vec3("center", None)?,
+                params.f32("radius", None)?,
+                params.material()?,
+            ))),
+            "volume" => {
+                let grid = match params.str("grid", None)? {
+                    "noise" => {
+                        let resolution = params.vec3("resolution", Some(Vec3::splat(64.0)))?;
+                        VoxelGrid::from_noise(
+                            [
+                                resolution.x() as usize,
+                                resolution.y() as usize,
+                                resolution.z() as usize,
+                            ],
+                            params.f32("frequency", Some(6.0))?,
+                            params.f32("octaves", Some(4.0))? as u32,
+                            params.f32("seed", Some(0.0))? as u32,
+                        )
+                    }
+                    grid_path => VoxelGrid::load_raw(&params.path(directory, grid_path))?,
+                };
+                scene.world.push(Box::new(Volume::new(
+                    Aabb::new(params.vec3("min", None)?, params.vec3("max", None)?),
+                    grid,
+                    params.f32("density", Some(1.0))?,
+                    params.vec3("albedo", Some(Vec3::one()))?,
+                )));
+            }
+            "environment" => {
+                scene.environment = match params.str("type", None)? {
+                    "gradient" => {
+                        let default = Gradient::default();
+                        Box::new(Gradient {
+                            bottom: params.vec3("bottom", Some(default.bottom))?,
+                            top: params.vec3("top", Some(default.top))?,
+                        })
+                    }
+                    "constant" => Box::new(Constant {
+                        color: params.vec3("color", None)?,
+                    }),
+                    "map" => Box::new(EnvironmentMap::load(
+                        &params.path(directory, params.str("path", None)?),
+                        params.f32("rotation", Some(0.0))?,
+                    )?),
+                    "sky" => Box::new(Sky::new(
+                        params.f32("elevation", Some(45.0))?,
+                        params.f32("azimuth", Some(0.0))?,
+                        params.f32("turbidity", Some(3.0))?,
+                    )),
+                    other => return Err(params.error(&format!("unknown environment {}", other))),
+                }
+            }
+            "light" => scene.lights.push(match params.str("type", None)? {
+                "point" => Light::Point {
+                    position: params.vec3("position", None)?,
+                    intensity: params.vec3("intensity", None)?,
+                },
+                "spot" => Light::Spot {
+                    position: params.vec3("position", None)?,
+                    direction: params.vec3("direction", None)?,
+                    intensity: params.vec3("intensity", None)?,
+                    inner_angle: params.f32("inner", None)?,
+                    outer_angle: params.f32("outer", None)?,
+                },
+                "directional" => Light::Directional {
+                    direction: params.vec3("direction", None)?,
+                    irradiance: params.vec3("irradiance", None)?,
+                },
+                other => return Err(params.error(&format!("unknown light {}", other))),
+            }),
+            "animate" => {
+                let (time, value) = (params.f32("time", None)?, params.vec3("value", None)?);
+                if !time.is_finite() {
+                    return Err(params.error("time isn't finite"));
+                }
+                if ![value.x(), value.y(), value.z()]
+                    .iter()
+                    .all(|c| c.is_finite())
+                {
+                    return Err(params.error("value isn't finite"));
+                }
+                scene.animation.insert(
+                    params.parameter()?,
+                    Key {
+                        time,
+                        value,
+                        interpolation: match params.str("interpolation", Some("linear"))? {
+                            "linear" => Interpolation::Linear,
+                            "bezier" => Interpolation::Bezier,
+                            other => {
+                                return Err(
+                                    params.error(&format!("unknown interpolation {}", other))
+                                )
+                            }
+                        },
+                    },
+                );
+            }
+            other => return Err(params.error(&format!("unknown keyword {}", other))),
+        }
+    }
+
+    let mut camera = camera.unwrap_or_else(|| {
+        Camera::new(
+            Vec3::new(0.0, 0.25, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            100.0,
+            1.0,
+            0.0,
+            1.0,
+        )
+    });
+    if autofocus {
+        camera.autofocus(&scene.world);
+        camera.refocus = true;
+    }
+
+    for track in &scene.animation.tracks {
+        let missing = match track.parameter {
+            Parameter::Center(object)
+            | Parameter::Radius(object)
+            | Parameter::Albedo(object)
+            | Parameter::Fuzz(object)
+            | Parameter::RefIdx(object)
+                if scene.sphere(object).is_none() =>
+            {
+                format!("animated object {} isn't a sphere", object)
+            }
+            Parameter::Intensity(light) if light >= scene.lights.len() => {
+                format!("animated light {} doesn't exist", light)
+            }
+            _ => continue,
+        };
+        return Err(io::Error::new(io::ErrorKind::InvalidData, missing));
+    }
+    Ok((scene, camera))
+}
+
+/// Writes the objects and animation of `scene` back into the scene file at `path`.
+/// Objects are written in world order, so that `animate` lines still point at them, and
+/// take the place of the file's object lines one by one, as tracks do with its `animate`
+/// lines. Volumes keep the lines they had in the file since they don't know where their
+/// grid came from. Every other line is kept as it was.
+pub fn save(path: &str, scene: &Scene) -> io::Result<()> {
+    let source = if Path::new(path).exists() {
+        fs::read_to_string(path)?
+    } else {
+        String::new()
+    };
+
+    let mut volumes = source
+        .lines()
+        .filter(|line| keyword(line) == Some("volume"));
+    let mut objects = Vec::new();
+    for (index, object) in scene.world.iter().enumerate() {
+        let line = match scene.sphere(index) {
+            Some(sphere) => format_sphere(sphere),
+            None if object.as_ref().as_any().is::<Volume>() => match volumes.next() {
+                Some(line) => line.to_string(),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("volume {} has no line in {} to keep", index, path),
+                    ))
+                }
+            },
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("object {} can't be written to a scene file", index),
+                ))
+            }
+        };
+        objects.push(line);
+    }
+    let mut objects = objects.into_iter();
+    let mut keys = scene
+        .animation
+        .tracks
+        .iter()
+        .flat_map(format_track)
+        .collect::<Vec<_>>()
+        .into_iter();
+
+    let mut lines = Vec::new();
+    for line in source.lines() {
+        match keyword(line) {
+            Some("sphere") | Some("volume") => lines.extend(objects.next()),
+            Some("animate") => lines.extend(keys.next()),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines.extend(objects);
+    lines.extend(keys);
+
+    fs::write(path, lines.join("\n") + "\n")
+}
+
+/// The keyword a line of a scene file starts with, if it isn't blank.
+fn keyword(line: &str) -> Option<&str> {
+    line.split('#').next().unwrap().split_whitespace().next()
+}
+
+fn format_vec3(v: Vec3) -> String {
+    format!("{},{},{}", v.x(), v.y(), v.z())
+}
+
+/// One `animate` line per key of `track`.
+fn format_track(track: &Track) -> Vec<String> {
+    let (target, vector) = match track.parameter {
+        Parameter::Center(object) => (format!("parameter=center object={}", object), true),
+        Parameter::Radius(object) => (format!("parameter=radius object={}", object), false),
+        Parameter::Albedo(object) => (format!("parameter=albedo object={}", object), true),
+        Parameter::Fuzz(object) => (format!("parameter=fuzz object={}", object), false),
+        Parameter::RefIdx(object) => (format!("parameter=ref_idx object={}", object), false),
+        Parameter::Intensity(light) => (format!("parameter=intensity light={}", light), true),
+        Parameter::Fov => ("parameter=fov".to_string(), false),
+        Parameter::Aperture => ("parameter=aperture".to_string(), false),
+    };
+    track
+        .keys
+        .iter()
+        .map(|key| {
+            let value = if vector {
+                format_vec3(key.value)
+            } else {
+                key.value.x().to_string()
+            };
+            let interpolation = match key.interpolation {
+                Interpolation::Linear => "linear",
+                Interpolation::Bezier => "bezier",
+            };
+            format!(
+                "animate {} time={} value={} interpolation={}",
+                target, key.time, value, interpolation
+            )
+        })
+        .collect()
+}
+
+fn format_sphere(sphere: &Sphere) -> String {
+    let material = match sphere.mat {
+        Material::Lambert { albedo } => format!("material=lambert albedo={}", format_vec3(albedo)),
+        Material::Metal { albedo, fuzz } => {
+            format!(
+                "material=metal albedo={} fuzz={}",
+                format_vec3(albedo),
+                fuzz
+            )
+        }
+        Material::Dielectric { albedo, ref_idx } => format!(
+            "material=dielectric albedo={} ref_idx={}",
+            format_vec3(albedo),
+            ref_idx
+        ),
+        Material::Isotropic { albedo } => {
+            format!("material=isotropic albedo={}", format_vec3(albedo))
+        }
+    };
+    format!(
+        "sphere center={} radius={} {}",
+        format_vec3(sphere.center),
+        sphere.radius,
+        material
+    )
+}
+
+struct Params<'a> {
+    line: usize,
+    keyword: &'a str,
+    values: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> Params<'a> {
+    fn parse(line: usize, text: &'a str) -> io::Result<Params<'a>> {
+        let mut tokens = text.split_whitespace();
+        let keyword = tokens.next().unwrap();
+        let mut params = Params {
+            line,
+            keyword,
+            values: HashMap::new(),
+        };
+        for token in tokens {
+            let mut pair = token.splitn(2, '=');
+            match (pair.next(), pair.next()) {
+                (Some(key), Some(value)) => {
+                    params.values.insert(key, value);
+                }
+                _ => return Err(params.error(&format!("expected key=value, found {}", token))),
+            }
+        }
+        Ok(params)
+    }
+
+    fn error(&self, message: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line {}: {}", self.line, message),
+        )
+    }
+
+    fn str(&self, key: &str, default: Option<&'a str>) -> io::Result<&'a str> {
+        match (self.values.get(key), default) {
+            (Some(value), _) => Ok(value),
+            (None, Some(default)) => Ok(default),
+            (None, None) => Err(self.error(&format!("{} is missing {}", self.keyword, key))),
+        }
+    }
+
+    fn f32(&self, key: &str, default: Option<f32>) -> io::Result<f32> {
+        match self.values.get(key) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| self.error(&format!("{} isn't a number", key))),
+            None => {
+                default.ok_or_else(|| self.error(&format!("{} is missing {}", self.keyword, key)))
+            }
+        }
+    }
+
+    fn vec3(&self, key: &str, default: Option<Vec3>) -> io::Result<Vec3> {
+        match self.values.get(key) {
+            Some(value) => {
+                let components = value
+                    .split(',')
+                    .map(|c| c.parse::<f32>())
+                    .collect::<Result<Vec<f32>, _>>()
+                    .map_err(|_| self.error(&format!("{} isn't a vector", key)))?;
+                match *components.as_slice() {
+                    [x, y, z] => Ok(Vec3::new(x, y, z)),
+                    [s] => Ok(Vec3::splat(s)),
+                    _ => Err(self.error(&format!("{} should have 3 components", key))),
+                }
+            }
+            None => {
+                default.ok_or_else(|| self.error(&format!("{} is missing {}", self.keyword, key)))
+            }
+        }
+    }
+
+    fn path(&self, directory: &Path, path: &str) -> String {
+        directory.join(path).to_string_lossy().into_owned()
+    }
+
+    fn material(&self) -> io::Result<Material> {
+        let albedo = self.vec3("albedo", Some(Vec3::one()))?;
+        match self.str("material", Some("lambert"))? {
+            "lambert" => Ok(Material::Lambert { albedo }),
+            "metal" => Ok(Material::Metal {
+                albedo,
+                fuzz: self.f32("fuzz", Some(0.0))?,
+            }),
+            "dielectric" => Ok(Material::Dielectric {
+                albedo,
+                ref_idx: self.f32("ref_idx", Some(1.5))?,
+            }),
+            "isotropic" => Ok(Material::Isotropic { albedo }),
+            other => Err(self.error(&format!("unknown material {}", other))),
+        }
+    }
+
+    fn parameter(&self) -> io::Result<Parameter> {
+        let object = || self.f32("object", None).map(|index| index as usize);
+        Ok(match self.str("parameter", None)? {
+            "center" => Parameter::Center(object()?),
+            "radius" => Parameter::Radius(object()?),
+            "albedo" => Parameter::Albedo(object()?),
+            "fuzz" => Parameter::Fuzz(object()?),
+            "ref_idx" => Parameter::RefIdx(object()?),
+            "intensity" => Parameter::Intensity(self.f32("light", None)? as usize),
+            "fov" => Parameter::Fov,
+            "aperture" => Parameter::Aperture,
+            other => return Err(self.error(&format!("unknown parameter {}", other))),
+        })
+    }
+
+    fn camera(&self, directory: &Path) -> io::Result<Camera> {
+        let fov = self.f32("fov", Some(90.0))?;
+        let origin = self.vec3("origin", Some(Vec3::zero()))?;
+        let aperture = self.f32("aperture", Some(0.0))?;
+        let focus_dist = self.f32("focus", Some(1.0))?;
+
+        // either yaw, pitch and roll in degrees or a point to look at
+        let mut camera = match self.values.get("rotation") {
+            Some(_) => {
+                let angles = self.vec3("rotation", None)?;
+                let rotation =
+                    Quat::from_rotation_ypr(deg(angles.x()), deg(angles.y()), deg(angles.z()));
+                Camera::from_rotation(origin, rotation, fov, 1.0, aperture, focus_dist)
+            }
+            None => Camera::new(
+                origin,
+                self.vec3("lookat", Some(origin - Vec3::unit_z()))?,
+                self.vec3("up", Some(Vec3::unit_y()))?,
+                fov,
+                1.0,
+                aperture,
+                focus_dist,
+            ),
+        };
+
+        if self.values.contains_key("focal_length") {
+            camera = camera.with_lens(&PhysicalLens {
+                sensor_width: self.f32("sensor_width", Some(36.0))?,
+                sensor_height: self.f32("sensor_height", Some(24.0))?,
+                focal_length: self.f32("focal_length", None)?,
+                f_stop: self.f32("f_stop", Some(8.0))?,
+            });
+        }
+
+        camera.aperture = match self.str("aperture_shape", Some("circle"))? {
+            "circle" => Aperture::Circle,
+            "polygon" => Aperture::Polygon {
+                blades: self.f32("blades", Some(6.0))? as u32,
+                rotation: self.f32("blade_rotation", Some(0.0))?,
+            },
+            mask_path => Aperture::Mask(Arc::new(ApertureMask::load(
+                &self.path(directory, mask_path),
+            )?)),
+        };
+        camera.anamorphic_squeeze = self.f32("squeeze", Some(1.0))?;
+        camera.cat_eye = self.f32("cat_eye", Some(0.0))?;
+        camera.focus_tilt = Vec2::new(
+            self.f32("tilt_x", Some(0.0))?,
+            self.f32("tilt_y", Some(0.0))?,
+        );
+
+        Ok(match self.str("model", Some("perspective"))? {
+            "perspective" => camera,
+            "orthographic" => camera.with_model(Arc::new(Orthographic {
+                height: self.f32("height", Some(2.0))?,
+            })),
+            "fisheye" => camera.with_model(Arc::new(Fisheye { fov })),
+            "equirectangular" => camera.with_model(Arc::new(Equirectangular)),
+            "stereo" => camera.with_model(Arc::new(StereoEquirectangular {
+                eye_separation: self.f32("eye_separation", Some(0.064))?,
+            })),
+            other => return Err(self.error(&format!("unknown camera model {}", other))),
+        })
+    }
+}
@@ -0,0 +1,297 @@
+use std::ops::{Add, BitAnd, BitOr, Div, Mul, Sub};
+
+/// Number of values `F32x4` works on at once.
+pub const LANES: usize = 4;
+
+pub use self::lanes::{F32x4, Mask4};
+
+/// SSE is always there on x86_64, so it needs no runtime detection.
+#[cfg(all(target_arch = "x86_64", not(feature = "scalar")))]
+mod lanes {
+    use super::LANES;
+    use std::arch::x86_64::*;
+
+    /// Four floats operated on together.
+    #[derive(Copy, Clone)]
+    pub struct F32x4(__m128);
+
+    /// Result of comparing two `F32x4` lane by lane.
+    #[derive(Copy, Clone)]
+    pub struct Mask4(__m128);
+
+    impl F32x4 {
+        pub fn new(values: [f32; LANES]) -> F32x4 {
+            unsafe { F32x4(_mm_loadu_ps(values.as_ptr())) }
+        }
+
+        pub fn splat(value: f32) -> F32x4 {
+            unsafe { F32x4(_mm_set1_ps(value)) }
+        }
+
+        pub fn to_array(self) -> [f32; LANES] {
+            let mut values = [0.0; LANES];
+            unsafe { _mm_storeu_ps(values.as_mut_ptr(), self.0) };
+            values
+        }
+
+        pub fn sqrt(self) -> F32x4 {
+            unsafe { F32x4(_mm_sqrt_ps(self.0)) }
+        }
+
+        pub fn min(self, other: F32x4) -> F32x4 {
+            unsafe { F32x4(_mm_min_ps(self.0, other.0)) }
+        }
+
+        pub fn max(self, other: F32x4) -> F32x4 {
+            unsafe { F32x4(_mm_max_ps(self.0, other.0)) }
+        }
+
+        pub fn lt(self, other: F32x4) -> Mask4 {
+            unsafe { Mask4(_mm_cmplt_ps(self.0, other.0)) }
+        }
+
+        pub fn gt(self, other: F32x4) -> Mask4 {
+            unsafe { Mask4(_mm_cmpgt_ps(self.0, other.0)) }
+        }
+    }
+
+    impl Mask4 {
+        pub fn new(values: [bool; LANES]) -> Mask4 {
+            let bits = |set: bool| if set { -1 } else { 0 };
+            unsafe {
+                Mask4(_mm_castsi128_ps(_mm_setr_epi32(
+                    bits(values[0]),
+                    bits(values[1]),
+                    bits(values[2]),
+                    bits(values[3]),
+                )))
+            }
+        }
+
+        /// One bit per lane, lane 0 in the lowest.
+        pub fn bits(self) -> u32 {
+            unsafe { _mm_movemask_ps(self.0) as u32 }
+        }
+
+        /// Lanes of `a` where the mask is set and of `b` elsewhere.
+        pub fn select(self, a: F32x4, b: F32x4) -> F32x4 {
+            unsafe {
+                F32x4(_mm_or_ps(
+                    _mm_and_ps(self.0, a.0),
+                    _mm_andnot_ps(self.0, b.0),
+                ))
+            }
+        }
+    }
+
+    pub fn add(a: F32x4, b: F32x4) -> F32x4 {
+        unsafe { F32x4(_mm_add_ps(a.0, b.0)) }
+    }
+
+    pub fn sub(a: F32x4, b: F32x4) -> F32x4 {
+        unsafe { F32x4(_mm_sub_ps(a.0, b.0)) }
+    }
+
+    pub fn mul(a: F32x4, b: F32x4) -> F32x4 {
+        unsafe { F32x4(_mm_mul_ps(a.0, b.0)) }
+    }
+
+    pub fn div(a: F32x4, b: F32x4) -> F32x4 {
+        unsafe { F32x4(_mm_div_ps(a.0, b.0)) }
+    }
+
+    pub fn and(a: Mask4, b: Mask4) -> Mask4 {
+        unsafe { Mask4(_mm_and_ps(a.0, b.0)) }
+    }
+
+    pub fn or(a: Mask4, b: Mask4) -> Mask4 {
+        unsafe { Mask4(_mm_or_ps(a.0, b.0)) }
+    }
+}
+
+/// One lane at a time, for other targets and to compare against with `--features scalar`.
+#[cfg(not(all(target_arch = "x86_64", not(feature = "scalar"))))]
+mod lanes {
+    use super::LANES;
+
+    /// Four floats operated on together.
+    #[derive(Copy, Clone)]
+    pub struct F32x4([f32; LANES]);
+
+    /// Result of comparing two `F32x4` lane by lane.
+    #[derive(Copy, Clone)]
+    pub struct Mask4([bool; LANES]);
+
+    fn map(a: F32x4, f: impl Fn(f32) -> f32) -> F32x4 {
+        F32x4([f(a.0[0]), f(a.0[1]), f(a.0[2]), f(a.0[3])])
+    }
+
+    fn zip(a: F32x4, b: F32x4, f: impl Fn(f32, f32) -> f32) -> F32x4 {
+        F32x4([
+            f(a.0[0], b.0[0]),
+            f(a.0[1], b.0[1]),
+            f(a.0[2], b.0[2]),
+            f(a.0[3], b.0[3]),
+        ])
+    }
+
+    fn compare(a: F32x4, b: F32x4, f: impl Fn(f32, f32) -> bool) -> Mask4 {
+        Mask4([
+            f(a.0[0], b.0[0]),
+            f(a.0[1], b.0[1]),
+            f(a.0[2], b.0[2]),
+            f(a.0[3], b.0[3]),
+        ])
+    }
+
+    impl F32x4 {
+        pub fn new(values: [f32; LANES]) -> F32x4 {
+            F32x4(values)
+        }
+
+        pub fn splat(value: f32) -> F32x4 {
+            F32x4([value; LANES])
+        }
+
+        pub fn to_array(self) -> [f32; LANES] {
+            self.0
+        }
+
+        pub fn sqrt(self) -> F32x4 {
+            map(self, f32::sqrt)
+        }
+
+        /// Like `minps`, the second operand when either is NaN.
+        pub fn min(self, other: F32x4) -> F32x4 {
+            zip(self, other, |a, b| if a < b { a } else { b })
+        }
+
+        pub fn max(self, other: F32x4) -> F32x4 {
+            zip(self, other, |a, b| if a > b { a } else { b })
+        }
+
+        pub fn lt(self, other: F32x4) -> Mask4 {
+            compare(self, other, |a, b| a < b)
+        }
+
+        pub fn gt(self, other: F32x4) -> Mask4 {
+            compare(self, other, |a, b| a > b)
+        }
+    }
+
+    impl Mask4 {
+        pub fn new(values: [bool; LANES]) -> Mask4 {
+            Mask4(values)
+        }
+
+        /// One bit per lane, lane 0 in the lowest.
+        pub fn bits(self) -> u32 {
+            (0..LANES)
+                .filter(|&lane| self.0[lane])
+                .map(|lane| 1 << lane)
+                .sum()
+        }
+
+        /// Lanes of `a` where the mask is set and of `b` elsewhere.
+        pub fn select(self, a: F32x4, b: F32x4) -> F32x4 {
+            let mut values = b.0;
+            for lane in 0..LANES {
+                if self.0[lane] {
+                    values[lane] = a.0[lane];
+                }
+            }
+            F32x4(values)
+        }
+    }
+
+    pub fn add(a: F32x4, b: F32x4) -> F32x4 {
+        zip(a, b, |a, b| a + b)
+    }
+
+    pub fn sub(a: F32x4, b: F32x4) -> F32x4 {
+        zip(a, b, |a, b| a - b)
+    }
+
+    pub fn mul(a: F32x4, b: F32x4) -> F32x4 {
+        zip(a, b, |a, b| a * b)
+    }
+
+    pub fn div(a: F32x4, b: F32x4) -> F32x4 {
+        zip(a, b, |a, b| a / b)
+    }
+
+    fn combine(a: Mask4, b: Mask4, f: impl Fn(bool, bool) -> bool) -> Mask4 {
+        Mask4([
+            f(a.0[0], b.0[0]),
+            f(a.0[1], b.0[1]),
+            f(a.0[2], b.0[2]),
+            f(a.0[3], b.0[3]),
+        ])
+    }
+
+    pub fn and(a: Mask4, b: Mask4) -> Mask4 {
+        combine(a, b, |a, b| a && b)
+    }
+
+    pub fn or(a: Mask4, b: Mask4) -> Mask4 {
+        combine(a, b, |a, b| a || b)
+    }
+}
+
+impl Mask4 {
+    pub fn any(self) -> bool {
+        self.bits() != 0
+    }
+
+    pub fn test(self, lane: usize) -> bool {
+        self.bits() & (1 << lane) != 0
+    }
+}
+
+impl Add for F32x4 {
+    type Output = F32x4;
+
+    fn add(self, other: F32x4) -> F32x4 {
+        lanes::add(self, other)
+    }
+}
+
+impl Sub for F32x4 {
+    type Output = F32x4;
+
+    fn sub(self, other: F32x4) -> F32x4 {
+        lanes::sub(self, other)
+    }
+}
+
+impl Mul for F32x4 {
+    type Output = F32x4;
+
+    fn mul(self, other: F32x4) -> F32x4 {
+        lanes::mul(self, other)
+    }
+}
+
+impl Div for F32x4 {
+    type Output = F32x4;
+
+    fn div(self, other: F32x4) -> F32x4 {
+        lanes::div(self, other)
+    }
+}
+
+impl BitAnd for Mask4 {
+    type Output = Mask4;
+
+    fn bitand(self, other: Mask4) -> Mask4 {
+        lanes::and(self, other)
+    }
+}
+
+impl BitOr for Mask4 {
+    type Output = Mask4;
+
+    fn bitor(self, other: Mask4) -> Mask4 {
+        lanes::or(self, other)
+    }
+}
@@ -0,0 +1,223 @@
+use crate::environment::Environment;
+use crate::math::orthonormal_basis;
+use glam::{Vec2, Vec3};
+use std::f32::consts::PI;
+
+/// Angular radius of the sun disc as seen from earth, in radians.
+pub const SUN_ANGULAR_RADIUS: f32 = 0.004_65;
+
+/// Radiance of the sun before atmospheric extinction, in the same units as the sky.
+const SUN_RADIANCE: f32 = 5e4;
+
+/// Preetham sky luminance is in kcd/m², this brings the light falling from a clear sky
+/// close to the one from the gradient.
+pub const SKY_SCALE: f32 = 0.04;
+
+/// Preetham et al. daylight model, with a sun disc that can be sampled directly.
+pub struct Sky {
+    sun_elevation: f32,
+    sun_azimuth: f32,
+    turbidity: f32,
+    sun_dir: Vec3,
+    cos_sun_radius: f32,
+    sun_radiance: Vec3,
+    // Perez coefficients and zenith values for Y, x and y
+    perez: [[f32; 5]; 3],
+    zenith: [f32; 3],
+}
+
+impl Sky {
+    /// Angles are in degrees. Azimuth 0 puts the sun towards -z, turbidity is usually in [2, 10].
+    pub fn new(sun_elevation: f32, sun_azimuth: f32, turbidity: f32) -> Sky {
+        let (elevation, azimuth) = (sun_elevation.to_radians(), sun_azimuth.to_radians());
+        let sun_dir = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+
+        // the model isn't defined with the sun below the horizon
+        let theta_s = (PI / 2.0 - elevation).clamp(0.0, PI / 2.0 - 0.01);
+        let t = turbidity;
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let (t2, th, th2, th3) = (
+            t * t,
+            theta_s,
+            theta_s * theta_s,
+            theta_s * theta_s * theta_s,
+        );
+        let zenith = [
+            (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192,
+            t2 * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+                + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+                + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886),
+            t2 * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+                + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+                + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688),
+        ];
+
+        let sun_radiance = if elevation > 0.0 {
+            SUN_RADIANCE * sun_transmittance(PI / 2.0 - elevation, turbidity)
+        } else {
+            Vec3::zero()
+        };
+
+        Sky {
+            sun_elevation,
+            sun_azimuth,
+            turbidity,
+            sun_dir,
+            cos_sun_radius: SUN_ANGULAR_RADIUS.cos(),
+            sun_radiance,
+            perez,
+            zenith,
+        }
+    }
+
+    pub fn sun_elevation(&self) -> f32 {
+        self.sun_elevation
+    }
+
+    pub fn sun_azimuth(&self) -> f32 {
+        self.sun_azimuth
+    }
+
+    pub fn turbidity(&self) -> f32 {
+        self.turbidity
+    }
+
+    pub fn sun_dir(&self) -> Vec3 {
+        self.sun_dir
+    }
+
+    /// Radiance of the sun disc after the atmosphere, zero below the horizon.
+    pub fn sun_radiance(&self) -> Vec3 {
+        self.sun_radiance
+    }
+
+    /// Perez coefficients for the distribution of Y, x and y over the sky.
+    pub fn perez(&self) -> [[f32; 5]; 3] {
+        self.perez
+    }
+
+    /// Y, x and y at the zenith, luminance still in kcd/m².
+    pub fn zenith(&self) -> [f32; 3] {
+        self.zenith
+    }
+
+    fn sun_pdf(&self) -> f32 {
+        1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius))
+    }
+
+    fn sky_radiance(&self, dir: Vec3) -> Vec3 {
+        // below the horizon keeps the horizon color
+        let cos_theta = dir.y().max(0.001);
+        let cos_gamma = Vec3::dot(dir, self.sun_dir).clamp(-1.0, 1.0);
+        let gamma = cos_gamma.acos();
+        let theta_s = (PI / 2.0 - self.sun_elevation.to_radians()).clamp(0.0, PI / 2.0 - 0.01);
+
+        let perez = |c: &[f32; 5], cos_theta: f32, gamma: f32, cos_gamma: f32| {
+            (1.0 + c[0] * (c[1] / cos_theta).exp())
+                * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * cos_gamma * cos_gamma)
+        };
+        let value = |i: usize| {
+            let c = &self.perez[i];
+            self.zenith[i] * perez(c, cos_theta, gamma, cos_gamma)
+                / perez(c, 1.0, theta_s, theta_s.cos())
+        };
+
+        let (luminance, x, y) = (value(0), value(1), value(2));
+        xyy_to_rgb(x, y, luminance * SKY_SCALE).max(Vec3::zero())
+    }
+}
+
+impl Environment for Sky {
+    fn radiance(&self, dir: Vec3) -> Vec3 {
+        let dir = dir.normalize();
+        let sky = self.sky_radiance(dir);
+        if Vec3::dot(dir, self.sun_dir) >= self.cos_sun_radius {
+            sky + self.sun_radiance
+        } else {
+            sky
+        }
+    }
+
+    /// Samples the sun cone uniformly, the rest of the sky is smooth enough to be
+    /// found by scattered rays.
+    fn sample(&self, u: Vec2) -> Option<(Vec3, f32)> {
+        if self.sun_radiance.max_element() <= 0.0 {
+            return None;
+        }
+
+        // the disc is so small that sin θ is taken from 1 - cos θ, which f32 keeps
+        // precisely, rather than from cos θ
+        let one_minus_cos = u.x() * (1.0 - self.cos_sun_radius);
+        let cos_theta = 1.0 - one_minus_cos;
+        let sin_theta = (one_minus_cos * (2.0 - one_minus_cos)).sqrt();
+        let phi = 2.0 * PI * u.y();
+        let (t, b) = orthonormal_basis(self.sun_dir);
+        let dir = sin_theta * phi.cos() * t + sin_theta * phi.sin() * b + cos_theta * self.sun_dir;
+
+        Some((dir, self.sun_pdf()))
+    }
+
+    fn pdf(&self, dir: Vec3) -> f32 {
+        if self.sun_radiance.max_element() > 0.0
+            && Vec3::dot(dir.normalize(), self.sun_dir) >= self.cos_sun_radius
+        {
+            self.sun_pdf()
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Rayleigh and aerosol extinction along the path of sunlight through the atmosphere,
+/// evaluated at representative wavelengths of the red, green and blue channels.
+fn sun_transmittance(theta_s: f32, turbidity: f32) -> Vec3 {
+    // relative optical air mass, Kasten's formula
+    let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+
+    let channel = |lambda_um: f32| {
+        let rayleigh = 0.008_735 * lambda_um.powf(-4.08);
+        let aerosol = beta * lambda_um.powf(-1.3);
+        (-air_mass * (rayleigh + aerosol)).exp()
+    };
+    Vec3::new(channel(0.65), channel(0.57), channel(0.475))
+}
+
+fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> Vec3 {
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    Vec3::new(
+        3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+    )
+}
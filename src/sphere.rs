@@ -3,16 +3,21 @@ use crate::material::Material;
 use crate::ray::Ray;
 use glam::f32::Vec3;
 
+#[derive(Clone)]
 pub struct Sphere {
+    /// Center when the shutter opens.
     pub center: Vec3,
     pub radius: f32,
     pub mat: Material,
+    /// How far the center moves while the shutter is open, for motion blur.
+    pub motion: Vec3,
 }
 
 impl Hittable for Sphere {
     fn hit(&self, r: &Ray, range: [f32; 2]) -> Option<Hit> {
         // t²*dot(dir,dir) + t*2*dot(dir, oc) + dot(oc, oc)-r² = 0
-        let oc = r.origin - self.center;
+        let center = self.center_at(r.time);
+        let oc = r.origin - center;
         let a = Vec3::dot(r.dir, r.dir);
         let b = 2.0 * Vec3::dot(r.dir, oc);
         let c = Vec3::dot(oc, oc) - self.radius * self.radius;
@@ -25,24 +30,26 @@ impl Hittable for Sphere {
         let t = (-b - delta.sqrt()) / (2.0 * a);
         if t > range[0] && t < range[1] {
             let pos = r.point_at(t);
-            let normal = (pos - self.center) / self.radius;
+            let normal = (pos - center) / self.radius;
             return Some(Hit {
                 t,
                 pos,
                 normal,
                 mat: self.mat.clone(),
+                object: 0,
             });
         }
 
         let t = (-b + delta.sqrt()) / (2.0 * a);
         if t > range[0] && t < range[1] {
             let pos = r.point_at(t);
-            let normal = (pos - self.center) / self.radius;
+            let normal = (pos - center) / self.radius;
             return Some(Hit {
                 t,
                 pos,
                 normal,
                 mat: self.mat.clone(),
+                object: 0,
             });
         }
         None
@@ -55,20 +62,11 @@ impl Sphere {
             center,
             radius,
             mat,
+            motion: Vec3::zero(),
         }
     }
-}
 
-impl Hittable for Vec<Sphere> {
-    fn hit(&self, r: &Ray, range: [f32; 2]) -> Option<Hit> {
-        let mut closest_hit = None;
-        let mut range = range;
-        for sphere in self {
-            if let Some(hit) = sphere.hit(r, range) {
-                range[1] = hit.t;
-                closest_hit = Some(hit);
-            }
-        }
-        closest_hit
+    pub fn center_at(&self, time: f32) -> Vec3 {
+        self.center + self.motion * time
     }
 }
@@ -0,0 +1,202 @@
+use crate::camera::Camera;
+use crate::hit::Hittable;
+use glam::{Vec2, Vec3};
+use rayon::prelude::*;
+use std::sync::Arc;
+
+/// Rays that escape are reprojected as if they hit something this far away.
+const FAR: f32 = 1e6;
+
+/// How far every pixel moved on the image since the `previous` camera, in image
+/// coordinates, found by tracing a pinhole ray through the center of each pixel. None
+/// where the previous camera couldn't have seen the point.
+pub fn motion_vectors(
+    previous: &Camera,
+    current: &Camera,
+    world: &dyn Hittable,
+    dimensions: (usize, usize),
+) -> Vec<Option<Vec2>> {
+    let (width, height) = dimensions;
+    (0..width * height)
+        .into_par_iter()
+        .map(|index| {
+            let uv = Vec2::new(
+                ((index % width) as f32 + 0.5) / width as f32,
+                ((index / width) as f32 + 0.5) / height as f32,
+            );
+            let ray = current.pinhole_ray(uv);
+            let point = match world.hit(&ray, [1e-3, f32::MAX]) {
+                Some(hit) => hit.pos,
+                None => ray.origin + ray.dir.normalize() * FAR,
+            };
+            previous.project(point).map(|previous_uv| uv - previous_uv)
+        })
+        .collect()
+}
+
+/// Blends every new frame with the previous ones. While the camera stays put this is the
+/// running average of every frame, converging to the true mean. Once it moves the history
+/// follows the camera with motion vectors and fades out exponentially, and history that
+/// doesn't resemble the neighborhood of the pixel in the new frame is clamped to it,
+/// which hides most of the ghosting from disocclusions.
+pub struct TemporalAccumulator {
+    /// Largest weight of the history in the blend while the camera moves.
+    pub history_weight: f32,
+    dimensions: (usize, usize),
+    history: Vec<Vec3>,
+    previous: Option<Camera>,
+    samples: u32,
+}
+
+impl TemporalAccumulator {
+    pub fn new(history_weight: f32) -> TemporalAccumulator {
+        TemporalAccumulator {
+            history_weight,
+            dimensions: (0, 0),
+            history: Vec::new(),
+            previous: None,
+            samples: 0,
+        }
+    }
+
+    /// Drops the history, the next frame starts over.
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.previous = None;
+        self.samples = 0;
+    }
+
+    /// Frames averaged into the history, or as many as the exponential blend is worth
+    /// while the camera moves.
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// The blend of the frames added so far.
+    pub fn image(&self) -> &[Vec3] {
+        &self.history
+    }
+
+    /// Adds a frame seen from `camera` and returns the blended image.
+    pub fn accumulate(
+        &mut self,
+        camera: &Camera,
+        world: &dyn Hittable,
+        dimensions: (usize, usize),
+        color: &[Vec3],
+    ) -> &[Vec3] {
+        let previous = match &self.previous {
+            Some(previous) if self.dimensions == dimensions => previous,
+            _ => {
+                self.dimensions = dimensions;
+                self.history = color.to_vec();
+                self.previous = Some(camera.clone());
+                self.samples = 1;
+                return &self.history;
+            }
+        };
+
+        if same_view(previous, camera) {
+            self.samples += 1;
+            let weight = 1.0 / self.samples as f32;
+            self.history
+                .par_iter_mut()
+                .zip(color)
+                .for_each(|(history, &current)| *history += weight * (current - *history));
+            return &self.history;
+        }
+
+        // as many frames as the exponential blend remembers
+        let max_samples = (1.0 / (1.0 - self.history_weight).max(1e-3)).round() as u32;
+        self.samples = (self.samples + 1).min(max_samples.max(1));
+
+        let motion = motion_vectors(previous, camera, world, dimensions);
+        let (width, height) = dimensions;
+        let history = &self.history;
+        let history_weight = 1.0 - 1.0 / self.samples as f32;
+
+        self.history = (0..width * height)
+            .into_par_iter()
+            .map(|index| {
+                let (x, y) = (index % width, index / width);
+                let current = color[index];
+
+                let uv = Vec2::new(
+                    (x as f32 + 0.5) / width as f32,
+                    (y as f32 + 0.5) / height as f32,
+                );
+                let previous_uv = match motion[index] {
+                    Some(motion) => uv - motion,
+                    None => return current,
+                };
+                if previous_uv.x() < 0.0
+                    || previous_uv.y() < 0.0
+                    || previous_uv.x() > 1.0
+                    || previous_uv.y() > 1.0
+                {
+                    return current;
+                }
+
+                let (low, high) = neighborhood(color, dimensions, x, y);
+                let reprojected = sample(history, dimensions, previous_uv).max(low).min(high);
+
+                current + history_weight * (reprojected - current)
+            })
+            .collect();
+        self.previous = Some(camera.clone());
+
+        &self.history
+    }
+}
+
+/// Whether two cameras see the scene alike, down to the projection and the lens. Models
+/// are compared by identity, changing the field of view makes a new one.
+fn same_view(a: &Camera, b: &Camera) -> bool {
+    a.origin == b.origin
+        && a.u == b.u
+        && a.v == b.v
+        && a.w == b.w
+        && a.aspect == b.aspect
+        && a.lens_radius == b.lens_radius
+        && a.focus_dist == b.focus_dist
+        && Arc::ptr_eq(&a.model, &b.model)
+        && a.aperture == b.aperture
+        && a.anamorphic_squeeze == b.anamorphic_squeeze
+        && a.cat_eye == b.cat_eye
+        && a.focus_tilt == b.focus_tilt
+}
+
+/// Smallest and largest value in the 3x3 block around a pixel.
+fn neighborhood(color: &[Vec3], dimensions: (usize, usize), x: usize, y: usize) -> (Vec3, Vec3) {
+    let (width, height) = dimensions;
+    let mut low = Vec3::splat(f32::MAX);
+    let mut high = Vec3::splat(f32::MIN);
+    for sample_y in y.saturating_sub(1)..(y + 2).min(height) {
+        for sample_x in x.saturating_sub(1)..(x + 2).min(width) {
+            let value = color[sample_x + sample_y * width];
+            low = low.min(value);
+            high = high.max(value);
+        }
+    }
+    (low, high)
+}
+
+/// Bilinear lookup with `uv` in [0, 1]², clamped to the edge pixels.
+fn sample(image: &[Vec3], dimensions: (usize, usize), uv: Vec2) -> Vec3 {
+    let (width, height) = dimensions;
+    let x = (uv.x() * width as f32 - 0.5)
+        .max(0.0)
+        .min((width - 1) as f32);
+    let y = (uv.y() * height as f32 - 0.5)
+        .max(0.0)
+        .min((height - 1) as f32);
+
+    let (x0, y0) = (x as usize, y as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (tx, ty) = (x - x0 as f32, y - y0 as f32);
+
+    let texel = |x: usize, y: usize| image[x + y * width];
+    let bottom = texel(x0, y0) * (1.0 - tx) + texel(x1, y0) * tx;
+    let top = texel(x0, y1) * (1.0 - tx) + texel(x1, y1) * tx;
+    bottom * (1.0 - ty) + top * ty
+}
@@ -1,115 +1,392 @@
+use crate::bvh::{self, SphereBvh};
 use crate::camera::Camera;
-use crate::hit::Hittable;
-use crate::material::Material;
+use crate::frame::{Aov, Frame};
+use crate::hit::{Hit, Hittable};
 use crate::ray::Ray;
-use crate::sphere::Sphere;
+use crate::rng;
+use crate::scene::Scene;
+use crate::simd::LANES;
 use glam::{Vec2, Vec3};
 use rayon::prelude::*;
 use std::fs::File;
 use std::io;
 use std::io::Write;
+use std::ops::Add;
 
-pub fn image(camera: &Camera, dimensions: (usize, usize), samples: i32, depth: i32) -> Vec<u8> {
-    let world = &vec![
-        Sphere::new(
-            Vec3::new(0.0, 0.0, -1.0),
-            0.5,
-            Material::Lambert {
-                albedo: Vec3::new(0.1, 0.2, 0.5),
-            },
-        ),
-        Sphere::new(
-            Vec3::new(0.0, -100.5, -1.0),
-            100.0,
-            Material::Lambert {
-                albedo: Vec3::new(0.6, 0.6, 0.4),
-            },
-        ),
-        Sphere::new(
-            Vec3::new(1.0, 0.0, -1.0),
-            0.5,
-            Material::Metal {
-                albedo: Vec3::new(0.8, 0.6, 0.2),
-                fuzz: 0.3,
-            },
-        ),
-        Sphere::new(
-            Vec3::new(-1.0, 0.0, -1.0),
-            0.5,
-            Material::Dielectric {
-                albedo: Vec3::new(0.9, 0.8, 0.8),
-                ref_idx: 1.5,
-            },
-        ),
-        Sphere::new(
-            Vec3::new(-1.0, 0.0, -1.0),
-            -0.45,
-            Material::Dielectric {
-                albedo: Vec3::one(),
-                ref_idx: 1.5,
-            },
-        ),
-    ];
+/// What to render and how hard to try.
+pub struct RenderSettings {
+    pub dimensions: (usize, usize),
+    pub samples: i32,
+    pub depth: i32,
+    /// Bounces every path takes before Russian roulette may end it, `None` to keep every
+    /// path going until `depth`.
+    pub roulette: Option<i32>,
+    /// Seeds the random numbers of every pixel, so that the same settings render the
+    /// same image. `None` draws different numbers every time.
+    pub seed: Option<u64>,
+    pub aovs: Vec<Aov>,
+}
+
+/// Rays traced for a frame.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RayCount {
+    pub camera: u64,
+    /// Rays continuing a path after it scattered.
+    pub bounce: u64,
+    /// Rays towards lights and the environment, only asking whether they're blocked.
+    pub shadow: u64,
+    /// Nodes of a `SphereBvh` opened to find what rays hit.
+    pub node_visits: u64,
+}
+
+impl RayCount {
+    pub fn total(&self) -> u64 {
+        self.camera + self.bounce + self.shadow
+    }
+
+    /// Rays that didn't start at the camera.
+    pub fn secondary(&self) -> u64 {
+        self.bounce + self.shadow
+    }
+
+    /// Average number of segments in a path.
+    pub fn path_length(&self) -> f32 {
+        (self.camera + self.bounce) as f32 / self.camera.max(1) as f32
+    }
+}
+
+impl Add for RayCount {
+    type Output = RayCount;
+
+    fn add(self, other: RayCount) -> RayCount {
+        RayCount {
+            camera: self.camera + other.camera,
+            bounce: self.bounce + other.bounce,
+            shadow: self.shadow + other.shadow,
+            node_visits: self.node_visits + other.node_visits,
+        }
+    }
+}
+
+pub fn image(
+    camera: &Camera,
+    scene: &Scene,
+    dimensions: (usize, usize),
+    samples: i32,
+    depth: i32,
+) -> Vec<u8> {
+    let settings = RenderSettings {
+        dimensions,
+        samples,
+        depth,
+        roulette: None,
+        seed: None,
+        aovs: Vec::new(),
+    };
+    to_rgba8(&render(camera, scene, &settings).color)
+}
 
-    let buffer = (0..dimensions.1)
+pub fn render(camera: &Camera, scene: &Scene, settings: &RenderSettings) -> Frame {
+    let (width, height) = settings.dimensions;
+    let samples = settings.samples;
+    // the samples of a pixel are coherent enough to find what they hit as packets, the
+    // rays after them go through the same tree one at a time
+    let spheres = SphereBvh::from_world(&scene.world);
+    let world: &dyn Hittable = match &spheres {
+        Some(spheres) => spheres,
+        None => &scene.world,
+    };
+
+    // color first, then every AOV in the order they were asked for
+    let pixels: Vec<(Vec<Vec3>, RayCount)> = (0..width * height)
         .into_par_iter()
-        .flat_map(|y| {
-            (0..dimensions.0).into_par_iter().flat_map(move |x| {
-                let mut color = Vec3::zero();
-                for _ in 0..samples {
-                    let offset = Vec2::new(rand::random(), rand::random());
-                    let uv = Vec2::new(
-                        (offset.x() + x as f32) / dimensions.0 as f32,
-                        (offset.y() + y as f32) / dimensions.1 as f32,
-                    );
-                    color += trace(&camera.get_ray(uv), &world, depth);
+        .map(|index| {
+            let (x, y) = (index % width, index / width);
+            if let Some(seed) = settings.seed {
+                rng::seed(&[seed, index as u64]);
+            }
+            let mut values = vec![Vec3::zero(); 1 + settings.aovs.len()];
+            let mut hits = 0;
+            let mut rays = RayCount::default();
+            let visits = bvh::node_visits();
+
+            for first in (0..samples).step_by(LANES) {
+                let packet: Vec<Ray> = (first..samples.min(first + LANES as i32))
+                    .map(|_| {
+                        let offset = Vec2::new(rng::random(), rng::random());
+                        let uv = Vec2::new(
+                            (offset.x() + x as f32) / width as f32,
+                            (offset.y() + y as f32) / height as f32,
+                        );
+                        camera.get_ray(uv)
+                    })
+                    .collect();
+                rays.camera += packet.len() as u64;
+                let first_hits = match &spheres {
+                    Some(spheres) => spheres.hit_packet(&packet, RANGE),
+                    None => packet.iter().map(|r| world.hit(r, RANGE)).collect(),
+                };
+
+                for (r, hit) in packet.iter().zip(first_hits) {
+                    let (hit, direct, indirect) = match hit {
+                        Some(hit) => {
+                            let (direct, indirect) =
+                                shade(r, &hit, scene, world, settings, 0, Vec3::one(), &mut rays);
+                            (Some(hit), direct, indirect)
+                        }
+                        None => (None, escaped(r, scene, None), Vec3::zero()),
+                    };
+                    values[0] += direct + indirect;
+
+                    for (value, &aov) in values[1..].iter_mut().zip(&settings.aovs) {
+                        *value += match (aov, &hit) {
+                            (Aov::Direct, _) => direct,
+                            (Aov::Indirect, _) => indirect,
+                            (_, None) => Vec3::zero(),
+                            (_, Some(hit)) => hit_aov(aov, hit, camera, hits == 0),
+                        };
+                    }
+                    if hit.is_some() {
+                        hits += 1;
+                    }
                 }
-                color /= samples as f32;
-
-                color = color.max(Vec3::zero());
-                color = color.min(Vec3::one());
-
-                const GAMMA: f32 = 2.2;
-                color = Vec3::new(
-                    color.x().powf(1.0 / GAMMA),
-                    color.y().powf(1.0 / GAMMA),
-                    color.z().powf(1.0 / GAMMA),
-                );
-
-                let (r, g, b, a) = (
-                    (color.x() * 255.99) as u8,
-                    (color.y() * 255.99) as u8,
-                    (color.z() * 255.99) as u8,
-                    255 as u8,
-                );
-
-                (0..4).into_par_iter().map(move |i| match i {
-                    0 => r,
-                    1 => g,
-                    2 => b,
-                    _ => a,
-                } as u8)
-            })
+            }
+
+            for (value, &aov) in values[1..].iter_mut().zip(&settings.aovs) {
+                *value = average_aov(aov, *value, samples, hits);
+            }
+            values[0] /= samples as f32;
+            rays.node_visits = bvh::node_visits() - visits;
+            (values, rays)
         })
         .collect();
 
-    buffer
+    let mut frame = Frame {
+        dimensions: settings.dimensions,
+        color: pixels.iter().map(|(values, _)| values[0]).collect(),
+        aovs: Vec::new(),
+        rays: pixels
+            .iter()
+            .fold(RayCount::default(), |total, &(_, rays)| total + rays),
+    };
+    for (index, &aov) in settings.aovs.iter().enumerate() {
+        let buffer = pixels.iter().map(|(values, _)| values[index + 1]).collect();
+        frame.aovs.push((aov, buffer));
+    }
+    frame
 }
 
-fn trace(r: &Ray, world: &Vec<Sphere>, depth: i32) -> Vec3 {
-    if let Some(hit) = world.hit(r, [1e-3, std::f32::MAX]) {
-        if depth > 0 {
-            let (attenuation, scattered) = hit.mat.scatter(r, &hit);
-            if let Some(ray) = scattered {
-                return attenuation * trace(&ray, world, depth - 1);
+/// What the first hit of a camera ray adds to one of the AOVs that describe the surface,
+/// the light AOVs are up to the integrator. `first_hit` is whether no earlier sample of the
+/// pixel hit anything.
+pub fn hit_aov(aov: Aov, hit: &Hit, camera: &Camera, first_hit: bool) -> Vec3 {
+    match aov {
+        Aov::Depth => Vec3::splat(Vec3::dot(hit.pos - camera.origin, -camera.w)),
+        Aov::Normal => hit.normal,
+        Aov::Position => hit.pos,
+        Aov::Albedo => hit.mat.albedo(),
+        // ids can't be blended, the first sample that hits decides
+        Aov::ObjectId if first_hit => id_value(hit.object as u64 + 1),
+        Aov::MaterialId if first_hit => id_value(u64::from(hit.mat.id())),
+        Aov::ObjectId | Aov::MaterialId | Aov::Direct | Aov::Indirect => Vec3::zero(),
+    }
+}
+
+/// An id as an AOV value. A f32 only holds integers up to 2^24 exactly, so larger ids are
+/// xor folded down to 24 bits, leaving out zero for the pixels that hit nothing.
+fn id_value(id: u64) -> Vec3 {
+    let mut folded = id;
+    while folded > 0x00ff_ffff {
+        folded = (folded >> 24) ^ (folded & 0x00ff_ffff);
+    }
+    Vec3::splat(folded.max(1) as f32)
+}
+
+/// Pixel value of an AOV from its sum over `samples`, `hits` of which hit something.
+pub fn average_aov(aov: Aov, sum: Vec3, samples: i32, hits: i32) -> Vec3 {
+    match aov {
+        Aov::ObjectId | Aov::MaterialId => sum,
+        // depth is only averaged over the samples that hit something
+        Aov::Depth if hits == 0 => Vec3::splat(f32::INFINITY),
+        Aov::Depth => sum / hits as f32,
+        _ => sum / samples as f32,
+    }
+}
+
+/// Clamps and gamma encodes linear radiance into RGBA bytes.
+pub fn to_rgba8(color: &[Vec3]) -> Vec<u8> {
+    color
+        .par_iter()
+        .flat_map(|&color| {
+            let mut color = color.max(Vec3::zero());
+            color = color.min(Vec3::one());
+
+            const GAMMA: f32 = 2.2;
+            color = Vec3::new(
+                color.x().powf(1.0 / GAMMA),
+                color.y().powf(1.0 / GAMMA),
+                color.z().powf(1.0 / GAMMA),
+            );
+
+            let (r, g, b, a) = (
+                (color.x() * 255.99) as u8,
+                (color.y() * 255.99) as u8,
+                (color.z() * 255.99) as u8,
+                255 as u8,
+            );
+
+            (0..4).into_par_iter().map(move |i| match i {
+                0 => r,
+                1 => g,
+                2 => b,
+                _ => a,
+            } as u8)
+        })
+        .collect()
+}
+
+/// Distances along a ray that count as hits, leaving out the surface it starts from.
+pub const RANGE: [f32; 2] = [1e-3, f32::MAX];
+
+/// Light leaving `hit` back along `r`, split into light that arrived straight from the
+/// lights and environment and light that bounced off something else first. `hit` is the
+/// vertex `bounce` of a path whose attenuation up to it is `throughput`. Rays are traced
+/// against `world`, the objects of `scene` or a tree over them.
+#[allow(clippy::too_many_arguments)]
+fn shade(
+    r: &Ray,
+    hit: &Hit,
+    scene: &Scene,
+    world: &dyn Hittable,
+    settings: &RenderSettings,
+    bounce: i32,
+    throughput: Vec3,
+    rays: &mut RayCount,
+) -> (Vec3, Vec3) {
+    if bounce >= settings.depth {
+        return (Vec3::zero(), Vec3::zero());
+    }
+
+    let (attenuation, scattered) = hit.mat.scatter(r, hit);
+    let ray = match scattered {
+        Some(ray) => ray,
+        None => return (Vec3::zero(), Vec3::zero()),
+    };
+
+    let pdf = hit.mat.eval(hit, ray.dir).map(|(_, pdf)| pdf);
+    let mut direct = match pdf {
+        Some(_) => {
+            sample_environment(scene, world, hit, r.time, rays)
+                + sample_lights(scene, world, hit, r.time, rays)
+        }
+        None => Vec3::zero(),
+    };
+
+    let attenuation = match roulette(settings, bounce, throughput * attenuation) {
+        Some(survival) => attenuation / survival,
+        None => return (direct, Vec3::zero()),
+    };
+
+    rays.bounce += 1;
+    match world.hit(&ray, RANGE) {
+        Some(next_hit) => {
+            let throughput = throughput * attenuation;
+            let (next_direct, next_indirect) = shade(
+                &ray,
+                &next_hit,
+                scene,
+                world,
+                settings,
+                bounce + 1,
+                throughput,
+                rays,
+            );
+            (direct, attenuation * (next_direct + next_indirect))
+        }
+        None => {
+            direct += attenuation * escaped(&ray, scene, pdf);
+            (direct, Vec3::zero())
+        }
+    }
+}
+
+/// Russian roulette for a path about to bounce off its vertex `bounce` with `throughput`:
+/// it goes on with the probability of its largest component, returned to weight what it
+/// brings back by, or ends with `None`.
+pub fn roulette(settings: &RenderSettings, bounce: i32, throughput: Vec3) -> Option<f32> {
+    match settings.roulette {
+        Some(bounces) if bounce >= bounces => {
+            let survival = throughput.max_element().min(1.0);
+            if rng::random::<f32>() < survival {
+                Some(survival)
+            } else {
+                None
             }
         }
-        return Vec3::zero();
+        _ => Some(1.0),
+    }
+}
+
+/// Radiance from the environment for a ray that left the scene. `bsdf_pdf` is the pdf of
+/// the bounce that scattered `r`, when that bounce also sampled the environment directly
+/// and the two strategies have to be weighted.
+pub fn escaped(r: &Ray, scene: &Scene, bsdf_pdf: Option<f32>) -> Vec3 {
+    let radiance = scene.environment.radiance(r.dir);
+    match bsdf_pdf {
+        Some(bsdf_pdf) => radiance * power_heuristic(bsdf_pdf, scene.environment.pdf(r.dir)),
+        None => radiance,
     }
+}
+
+fn sample_environment(
+    scene: &Scene,
+    world: &dyn Hittable,
+    hit: &Hit,
+    time: f32,
+    rays: &mut RayCount,
+) -> Vec3 {
+    let u = Vec2::new(rng::random(), rng::random());
+    if let Some((dir, light_pdf)) = scene.environment.sample(u) {
+        if let Some((f, bsdf_pdf)) = hit.mat.eval(hit, dir) {
+            if f.max_element() > 0.0 {
+                let shadow_ray = Ray::at_time(hit.pos, dir, time);
+                rays.shadow += 1;
+                let transmittance = world.transmittance(&shadow_ray, RANGE);
+                let weight = power_heuristic(light_pdf, bsdf_pdf);
+                return f * scene.environment.radiance(dir) * transmittance * weight / light_pdf;
+            }
+        }
+    }
+    Vec3::zero()
+}
+
+/// Delta lights can only be reached through shadow rays, so they get no MIS weight.
+fn sample_lights(
+    scene: &Scene,
+    world: &dyn Hittable,
+    hit: &Hit,
+    time: f32,
+    rays: &mut RayCount,
+) -> Vec3 {
+    let mut direct = Vec3::zero();
+    for light in &scene.lights {
+        if let Some((dir, distance, radiance)) = light.sample(hit.pos) {
+            if let Some((f, _)) = hit.mat.eval(hit, dir) {
+                if f.max_element() > 0.0 {
+                    let shadow_ray = Ray::at_time(hit.pos, dir, time);
+                    rays.shadow += 1;
+                    let transmittance = world.transmittance(&shadow_ray, [1e-3, distance]);
+                    direct += f * radiance * transmittance;
+                }
+            }
+        }
+    }
+    direct
+}
 
-    let dir = r.dir.normalize();
-    let t = dir.y() * 0.5 + 0.5;
-    (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0)
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    a / (a + b)
 }
 
 pub fn write_to_tga(path: &str, dimensions: (usize, usize), buffer: &mut [u8]) -> io::Result<()> {
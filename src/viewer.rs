@@ -0,0 +1,255 @@
+use crate::camera::Camera;
+use crate::camera_path::CameraPath;
+use crate::denoise::Denoiser;
+use crate::frame::Aov;
+use crate::gpu::GpuTracer;
+use crate::hit::Hittable;
+use crate::scene::Scene;
+use crate::temporal::TemporalAccumulator;
+use crate::trace::{self, RayCount, RenderSettings};
+use glam::{deg, Quat, Vec3};
+use rayon::prelude::*;
+use std::io;
+
+/// How movement input drives the camera.
+pub enum Controller {
+    /// Free flight along the view.
+    Fly,
+    /// Turntable around `target`, forward and back change the distance to it.
+    Orbit { target: Vec3, distance: f32 },
+}
+
+/// Camera pose driven by look and movement input, for the realtime viewer and its
+/// headless runs alike.
+pub struct CameraController {
+    pub origin: Vec3,
+    /// Yaw, pitch and roll in degrees.
+    pub angles: Vec3,
+    pub controller: Controller,
+}
+
+impl CameraController {
+    /// Flies from the pose of `camera`.
+    pub fn new(camera: &Camera) -> CameraController {
+        CameraController {
+            origin: camera.origin,
+            angles: angles(camera.u, camera.w),
+            controller: Controller::Fly,
+        }
+    }
+
+    /// Moves to `origin` and turns to `rotation`, as when replaying a recorded path.
+    pub fn set_pose(&mut self, origin: Vec3, rotation: Quat) {
+        self.origin = origin;
+        self.angles = angles(rotation * Vec3::unit_x(), rotation * Vec3::unit_z());
+    }
+
+    pub fn rotation(&self) -> Quat {
+        Quat::from_rotation_ypr(
+            deg(self.angles.x()),
+            deg(self.angles.y()),
+            deg(self.angles.z()),
+        )
+    }
+
+    /// Turns by `look` degrees and moves along `movement`, in the camera's local axes with
+    /// -z forward, at `speed` units per second.
+    pub fn update(&mut self, look: Vec3, movement: Vec3, speed: f32, dt: f32) {
+        self.angles += look;
+        if let Controller::Orbit { .. } = self.controller {
+            // sideways and vertical movement goes around the target
+            self.angles += 90.0 * speed * dt * Vec3::new(movement.x(), -movement.y(), 0.0);
+        }
+        self.angles.set_y(self.angles.y().clamp(-85.0, 85.0));
+
+        let rotation = self.rotation();
+        match &mut self.controller {
+            Controller::Fly => self.origin += rotation * (speed * movement * dt),
+            Controller::Orbit { target, distance } => {
+                *distance = (*distance * (1.0 + speed * movement.z() * dt)).max(1e-2);
+                self.origin = *target + rotation * Vec3::new(0.0, 0.0, *distance);
+            }
+        }
+    }
+
+    /// Starts orbiting `target` from where the camera is, or goes back to flying.
+    pub fn toggle_orbit(&mut self, target: Vec3) {
+        self.controller = match self.controller {
+            Controller::Fly => Controller::Orbit {
+                target,
+                distance: (target - self.origin).length(),
+            },
+            Controller::Orbit { .. } => Controller::Fly,
+        };
+    }
+
+    /// `base` moved to the controller's pose, and focused on the center of the view if it
+    /// refocuses.
+    pub fn camera(&self, base: &Camera, aspect: f32, world: &dyn Hittable) -> Camera {
+        let mut camera = base.clone();
+        camera.set_pose(self.origin, self.rotation());
+        camera.aspect = aspect;
+        if camera.refocus {
+            camera.autofocus(world);
+        }
+        camera
+    }
+}
+
+/// Yaw, pitch and roll in degrees of a camera with right axis `u` and backward axis `w`,
+/// the inverse of `CameraController::rotation`.
+fn angles(u: Vec3, w: Vec3) -> Vec3 {
+    let forward = -w;
+    let yaw = (-forward.x()).atan2(-forward.z()).to_degrees();
+    let pitch = forward.y().clamp(-1.0, 1.0).asin().to_degrees();
+    // the axes before rolling
+    let level = Quat::from_rotation_ypr(deg(yaw), deg(pitch), deg(0.0));
+    let roll = Vec3::dot(u, level * Vec3::unit_y()).atan2(Vec3::dot(u, level * Vec3::unit_x()));
+    Vec3::new(yaw, pitch, roll.to_degrees())
+}
+
+/// What traces the viewer's frames.
+pub enum Backend {
+    Cpu,
+    /// Falls back to the CPU for good the first time it can't render a frame.
+    Gpu(Box<GpuTracer>),
+}
+
+/// The realtime pipeline: one sample per pixel blended with the frames before it, then
+/// denoised.
+pub struct Viewer {
+    pub depth: i32,
+    /// See `RenderSettings::roulette`.
+    pub roulette: Option<i32>,
+    pub denoise: bool,
+    /// Frames of history after which the accumulated image is left as it is, the denoiser
+    /// fades out until then so a still view converges to the unbiased mean.
+    pub denoise_samples: u32,
+    pub denoiser: Denoiser,
+    pub accumulator: TemporalAccumulator,
+    pub backend: Backend,
+    /// The last accumulated image, denoised.
+    denoised: Vec<Vec3>,
+}
+
+impl Default for Viewer {
+    fn default() -> Self {
+        Viewer {
+            depth: 50,
+            roulette: Some(3),
+            denoise: true,
+            denoise_samples: 64,
+            denoiser: Denoiser::default(),
+            accumulator: TemporalAccumulator::new(0.9),
+            backend: Backend::Cpu,
+            denoised: Vec::new(),
+        }
+    }
+}
+
+impl Viewer {
+    /// Renders what `camera` sees into the accumulated image, returning it as linear
+    /// radiance along with the rays it took.
+    pub fn frame(
+        &mut self,
+        camera: &Camera,
+        scene: &Scene,
+        dimensions: (usize, usize),
+    ) -> (&[Vec3], RayCount) {
+        let settings = RenderSettings {
+            dimensions,
+            samples: 1,
+            depth: self.depth,
+            roulette: self.roulette,
+            seed: None,
+            aovs: if self.denoise {
+                vec![Aov::Albedo, Aov::Normal, Aov::Depth]
+            } else {
+                Vec::new()
+            },
+        };
+        let gpu_frame = match &mut self.backend {
+            Backend::Cpu => None,
+            Backend::Gpu(tracer) => match tracer.render(camera, scene, &settings) {
+                Ok(frame) => Some(frame),
+                Err(error) => {
+                    eprintln!("GPU tracer: {}, switching to the CPU", error);
+                    None
+                }
+            },
+        };
+        if gpu_frame.is_none() {
+            self.backend = Backend::Cpu;
+        }
+        let mut frame = gpu_frame.unwrap_or_else(|| trace::render(camera, scene, &settings));
+
+        // history follows the camera, so it only has to be dropped when the scene changes.
+        // It holds raw radiance so it still converges, the denoiser only sees the blend
+        self.accumulator
+            .accumulate(camera, &scene.world, dimensions, &frame.color);
+        let color = self.accumulator.image();
+        let fade = self.accumulator.samples() as f32 / self.denoise_samples.max(1) as f32;
+        if !self.denoise || fade >= 1.0 {
+            return (color, frame.rays);
+        }
+        frame.color = color.to_vec();
+        self.denoised = self.denoiser.denoise(&frame);
+        for (denoised, &color) in self.denoised.iter_mut().zip(color) {
+            *denoised += fade * (color - *denoised);
+        }
+        (&self.denoised, frame.rays)
+    }
+
+    /// Flies a camera along `path` through the same pipeline as the window, without one.
+    /// `output` gets the index of every frame at `fps` and the frame composited, rows
+    /// starting from the top as in images.
+    pub fn replay(
+        &mut self,
+        scene: &Scene,
+        base_camera: &Camera,
+        path: &CameraPath,
+        dimensions: (usize, usize),
+        fps: f32,
+        mut output: impl FnMut(usize, &[u8]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let mut controller = CameraController::new(base_camera);
+        let aspect = dimensions.0 as f32 / dimensions.1 as f32;
+        let frames = (path.duration() * fps).floor() as usize + 1;
+
+        for index in 0..frames {
+            if let Some((origin, rotation)) = path.pose_at(index as f32 / fps) {
+                controller.set_pose(origin, rotation);
+            }
+            let camera = controller.camera(base_camera, aspect, &scene.world);
+            let (color, _) = self.frame(&camera, scene, dimensions);
+
+            let pixels = composite(color);
+            let rows: Vec<u8> = pixels
+                .chunks(dimensions.0 * 4)
+                .rev()
+                .flatten()
+                .cloned()
+                .collect();
+            output(index, &rows)?;
+        }
+        Ok(())
+    }
+}
+
+/// Radiance clamped and sRGB encoded into RGBA bytes, as the viewer presents it.
+pub fn composite(color: &[Vec3]) -> Vec<u8> {
+    let encode = |c: f32| {
+        let c = c.clamp(0.0, 1.0);
+        let srgb = if c <= 0.003_130_8 {
+            12.92 * c
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        };
+        (srgb * 255.0).round() as u8
+    };
+    let mut pixels = vec![255; 4 * color.len()];
+    pixels.par_chunks_mut(4).zip(color).for_each(|(pixel, c)| {
+        pixel[..3].copy_from_slice(&[encode(c.x()), encode(c.y()), encode(c.z())])
+    });
+    pixels
+}
@@ -0,0 +1,281 @@
+use crate::aabb::Aabb;
+use crate::hit::{Hit, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::rng;
+use glam::f32::Vec3;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+
+/// Density samples on a regular grid, stored with x varying fastest, then y, then z.
+pub struct VoxelGrid {
+    pub resolution: [usize; 3],
+    pub data: Vec<f32>,
+}
+
+impl VoxelGrid {
+    pub fn new(resolution: [usize; 3], data: Vec<f32>) -> VoxelGrid {
+        assert_eq!(resolution[0] * resolution[1] * resolution[2], data.len());
+        VoxelGrid { resolution, data }
+    }
+
+    /// Cloud-like density from fractal value noise, faded out towards the edges of the grid.
+    pub fn from_noise(
+        resolution: [usize; 3],
+        frequency: f32,
+        octaves: u32,
+        seed: u32,
+    ) -> VoxelGrid {
+        let mut data = Vec::with_capacity(resolution[0] * resolution[1] * resolution[2]);
+        for z in 0..resolution[2] {
+            for y in 0..resolution[1] {
+                for x in 0..resolution[0] {
+                    let p = Vec3::new(
+                        (x as f32 + 0.5) / resolution[0] as f32,
+                        (y as f32 + 0.5) / resolution[1] as f32,
+                        (z as f32 + 0.5) / resolution[2] as f32,
+                    );
+                    let falloff = (1.0 - 2.0 * (p - Vec3::splat(0.5)).length()).max(0.0);
+                    let noise = fractal_noise(p * frequency, octaves, seed);
+                    data.push((2.0 * noise * falloff - 0.25).max(0.0));
+                }
+            }
+        }
+        VoxelGrid::new(resolution, data)
+    }
+
+    /// Loads a grid from a raw file: three little-endian u32 with the resolution
+    /// followed by the little-endian f32 densities.
+    pub fn load_raw(path: &str) -> io::Result<VoxelGrid> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+
+        let word = |i: usize| {
+            [
+                bytes[4 * i],
+                bytes[4 * i + 1],
+                bytes[4 * i + 2],
+                bytes[4 * i + 3],
+            ]
+        };
+        if bytes.len() < 12 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "voxel grid is missing its header",
+            ));
+        }
+
+        let resolution = [
+            u32::from_le_bytes(word(0)) as usize,
+            u32::from_le_bytes(word(1)) as usize,
+            u32::from_le_bytes(word(2)) as usize,
+        ];
+        if resolution.contains(&0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "voxel grid is empty",
+            ));
+        }
+        // a corrupt header can ask for more voxels than there are numbers for
+        let count = resolution[0]
+            .checked_mul(resolution[1])
+            .and_then(|n| n.checked_mul(resolution[2]));
+        let size = count
+            .and_then(|n| n.checked_add(3))
+            .and_then(|n| n.checked_mul(4));
+        let count = match (count, size) {
+            (Some(count), Some(size)) if size == bytes.len() => count,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "voxel grid size doesn't match its header",
+                ))
+            }
+        };
+
+        let data: Vec<f32> = (0..count)
+            .map(|i| f32::from_le_bytes(word(3 + i)))
+            .collect();
+        // tracking against the largest density needs every one to be finite and not negative
+        if data
+            .iter()
+            .any(|density| !density.is_finite() || *density < 0.0)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "voxel grid has a negative or invalid density",
+            ));
+        }
+        Ok(VoxelGrid::new(resolution, data))
+    }
+
+    pub fn write_raw(&self, path: &str) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        for &n in &self.resolution {
+            file.write_all(&(n as u32).to_le_bytes())?;
+        }
+        for &density in &self.data {
+            file.write_all(&density.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn max_density(&self) -> f32 {
+        self.data.iter().cloned().fold(0.0, f32::max)
+    }
+
+    /// Trilinearly interpolated density at `p` in grid space, [0, 1] on every axis.
+    pub fn sample(&self, p: Vec3) -> f32 {
+        let axis = |v: f32, n: usize| {
+            // voxel centers sit at (i + 0.5) / n
+            let f = (v * n as f32 - 0.5).max(0.0).min((n - 1) as f32);
+            let i0 = f as usize;
+            (i0, (i0 + 1).min(n - 1), f - i0 as f32)
+        };
+        let (x0, x1, fx) = axis(p.x(), self.resolution[0]);
+        let (y0, y1, fy) = axis(p.y(), self.resolution[1]);
+        let (z0, z1, fz) = axis(p.z(), self.resolution[2]);
+
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let plane = |z| {
+            lerp(
+                lerp(self.voxel(x0, y0, z), self.voxel(x1, y0, z), fx),
+                lerp(self.voxel(x0, y1, z), self.voxel(x1, y1, z), fx),
+                fy,
+            )
+        };
+        lerp(plane(z0), plane(z1), fz)
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.data[x + self.resolution[0] * (y + self.resolution[1] * z)]
+    }
+}
+
+/// Heterogeneous participating medium filling `bounds` with density from a voxel grid.
+pub struct Volume {
+    pub bounds: Aabb,
+    pub albedo: Vec3,
+    grid: VoxelGrid,
+    density_scale: f32,
+    majorant: f32,
+}
+
+impl Volume {
+    pub fn new(bounds: Aabb, grid: VoxelGrid, density_scale: f32, albedo: Vec3) -> Volume {
+        let majorant = grid.max_density() * density_scale;
+        Volume {
+            bounds,
+            albedo,
+            grid,
+            density_scale,
+            majorant,
+        }
+    }
+
+    pub fn grid(&self) -> &VoxelGrid {
+        &self.grid
+    }
+
+    pub fn density_at(&self, pos: Vec3) -> f32 {
+        let p = (pos - self.bounds.min) / self.bounds.size();
+        self.density_scale * self.grid.sample(p)
+    }
+}
+
+impl Hittable for Volume {
+    /// Delta tracking: samples tentative collisions against the majorant and accepts
+    /// them with probability density / majorant.
+    fn hit(&self, r: &Ray, range: [f32; 2]) -> Option<Hit> {
+        let [mut t, t_exit] = self.bounds.hit(r, range)?;
+        if self.majorant <= 0.0 {
+            return None;
+        }
+
+        let inv_majorant = 1.0 / (self.majorant * r.dir.length());
+        loop {
+            t -= (1.0 - rng::random::<f32>()).ln() * inv_majorant;
+            if t >= t_exit {
+                return None;
+            }
+
+            let pos = r.point_at(t);
+            if self.density_at(pos) > rng::random::<f32>() * self.majorant {
+                return Some(Hit {
+                    t,
+                    pos,
+                    normal: -r.dir.normalize(),
+                    mat: Material::Isotropic {
+                        albedo: self.albedo,
+                    },
+                    object: 0,
+                });
+            }
+        }
+    }
+
+    /// Ratio tracking estimate of the transmittance, cheaper and less noisy than
+    /// testing for a delta tracked collision.
+    fn transmittance(&self, r: &Ray, range: [f32; 2]) -> f32 {
+        let [mut t, t_exit] = match self.bounds.hit(r, range) {
+            Some(interval) => interval,
+            None => return 1.0,
+        };
+        if self.majorant <= 0.0 {
+            return 1.0;
+        }
+
+        // t is measured in units of the (unnormalized) ray direction
+        let inv_majorant = 1.0 / (self.majorant * r.dir.length());
+        let mut transmittance = 1.0;
+        loop {
+            t -= (1.0 - rng::random::<f32>()).ln() * inv_majorant;
+            if t >= t_exit {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.density_at(r.point_at(t)) / self.majorant;
+        }
+    }
+}
+
+fn hash(x: i32, y: i32, z: i32, seed: u32) -> f32 {
+    let mut h = seed
+        .wrapping_add((x as u32).wrapping_mul(0x8da6_b343))
+        .wrapping_add((y as u32).wrapping_mul(0xd816_3841))
+        .wrapping_add((z as u32).wrapping_mul(0xcb1a_b31f));
+    h = (h ^ (h >> 13)).wrapping_mul(0x5bd1_e995);
+    h ^= h >> 15;
+    (h & 0x00ff_ffff) as f32 / 0x0100_0000 as f32
+}
+
+fn value_noise(p: Vec3, seed: u32) -> f32 {
+    let (x, y, z) = (p.x().floor(), p.y().floor(), p.z().floor());
+    let (xi, yi, zi) = (x as i32, y as i32, z as i32);
+    let fade = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (fx, fy, fz) = (fade(p.x() - x), fade(p.y() - y), fade(p.z() - z));
+
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let plane = |z| {
+        lerp(
+            lerp(hash(xi, yi, z, seed), hash(xi + 1, yi, z, seed), fx),
+            lerp(hash(xi, yi + 1, z, seed), hash(xi + 1, yi + 1, z, seed), fx),
+            fy,
+        )
+    };
+    lerp(plane(zi), plane(zi + 1), fz)
+}
+
+fn fractal_noise(p: Vec3, octaves: u32, seed: u32) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 0.5;
+    let mut total_amplitude = 0.0;
+    let mut p = p;
+    for octave in 0..octaves {
+        sum += amplitude * value_noise(p, seed.wrapping_add(octave));
+        total_amplitude += amplitude;
+        amplitude *= 0.5;
+        p *= 2.0;
+    }
+    sum / total_amplitude
+}
@@ -0,0 +1,381 @@
+use crate::bvh::{self, SphereBvh};
+use crate::camera::Camera;
+use crate::frame::{Aov, Frame};
+use crate::hit::{Hit, Hittable};
+use crate::ray::Ray;
+use crate::rng;
+use crate::scene::Scene;
+use crate::trace::{self, RayCount, RenderSettings, RANGE};
+use glam::{Vec2, Vec3};
+use rayon::prelude::*;
+
+/// Iterative path tracer that takes every path of a pass through one stage at a time:
+/// camera rays are generated for every pixel, extended to what they hit, shaded into the
+/// next bounce and shadow rays, and the shadow rays connected to the lights. It matches
+/// `trace::render` statistically, without recursing once per bounce.
+pub struct Wavefront {
+    /// Reorders the queues between stages, hits by material and rays by direction, so
+    /// neighbouring work takes the same branches and touches the same data.
+    pub sort: bool,
+}
+
+impl Default for Wavefront {
+    fn default() -> Self {
+        Wavefront { sort: true }
+    }
+}
+
+/// Paths waiting for their next ray to be traced, a component per vector.
+#[derive(Default)]
+struct PathQueue {
+    origin: Vec<Vec3>,
+    dir: Vec<Vec3>,
+    time: Vec<f32>,
+    /// Product of the attenuations along the path so far.
+    throughput: Vec<Vec3>,
+    /// Pdf of the bounce that made the ray, when that bounce also sampled the environment
+    /// and the two have to be weighted.
+    bsdf_pdf: Vec<Option<f32>>,
+    pixel: Vec<usize>,
+}
+
+impl PathQueue {
+    fn push(&mut self, ray: &Ray, throughput: Vec3, bsdf_pdf: Option<f32>, pixel: usize) {
+        self.origin.push(ray.origin);
+        self.dir.push(ray.dir);
+        self.time.push(ray.time);
+        self.throughput.push(throughput);
+        self.bsdf_pdf.push(bsdf_pdf);
+        self.pixel.push(pixel);
+    }
+
+    fn len(&self) -> usize {
+        self.pixel.len()
+    }
+
+    fn ray(&self, index: usize) -> Ray {
+        Ray::at_time(self.origin[index], self.dir[index], self.time[index])
+    }
+
+    /// The same paths, the one at `order[i]` moved to `i`.
+    fn reordered(&self, order: &[usize]) -> PathQueue {
+        let mut queue = PathQueue::default();
+        for &index in order {
+            queue.push(
+                &self.ray(index),
+                self.throughput[index],
+                self.bsdf_pdf[index],
+                self.pixel[index],
+            );
+        }
+        queue
+    }
+}
+
+/// Shadow rays, with the radiance each brings to its pixel if nothing blocks it.
+#[derive(Default)]
+struct ShadowQueue {
+    origin: Vec<Vec3>,
+    dir: Vec<Vec3>,
+    time: Vec<f32>,
+    distance: Vec<f32>,
+    radiance: Vec<Vec3>,
+    pixel: Vec<usize>,
+    /// Whether the light arrives at the first hit, or bounced before.
+    direct: Vec<bool>,
+}
+
+/// A light or environment sample seen from a hit, before the shadow ray is traced.
+struct Connection {
+    dir: Vec3,
+    distance: f32,
+    radiance: Vec3,
+}
+
+/// What shading a hit leaves to the next stages.
+#[derive(Default)]
+struct Shaded {
+    /// Scattered ray, its attenuation and the pdf it has when it can be weighted against
+    /// light samples.
+    next: Option<(Ray, Vec3, Option<f32>)>,
+    connections: Vec<Connection>,
+}
+
+/// Radiance reaching every pixel, split like the direct and indirect AOVs.
+struct Film {
+    direct: Vec<Vec3>,
+    indirect: Vec<Vec3>,
+}
+
+impl Film {
+    fn add(&mut self, pixel: usize, direct: bool, radiance: Vec3) {
+        if direct {
+            self.direct[pixel] += radiance;
+        } else {
+            self.indirect[pixel] += radiance;
+        }
+    }
+}
+
+impl Wavefront {
+    /// Same as `trace::render`.
+    pub fn render(&self, camera: &Camera, scene: &Scene, settings: &RenderSettings) -> Frame {
+        let (width, height) = settings.dimensions;
+        let pixels = width * height;
+        let spheres = SphereBvh::from_world(&scene.world);
+        let world: &dyn Hittable = match &spheres {
+            Some(spheres) => spheres,
+            None => &scene.world,
+        };
+
+        let mut film = Film {
+            direct: vec![Vec3::zero(); pixels],
+            indirect: vec![Vec3::zero(); pixels],
+        };
+        let mut aovs = vec![vec![Vec3::zero(); pixels]; settings.aovs.len()];
+        let mut hit_counts = vec![0; pixels];
+        let mut rays = RayCount::default();
+
+        for sample in 0..settings.samples {
+            // seeds every stage of every bounce apart, each item of a stage by its key
+            let reseed = |bounce: i32, stage: u64, key: usize| {
+                if let Some(seed) = settings.seed {
+                    rng::seed(&[seed, sample as u64, bounce as u64, stage, key as u64]);
+                }
+            };
+
+            // generate
+            let camera_rays: Vec<Ray> = (0..pixels)
+                .into_par_iter()
+                .map(|index| {
+                    reseed(0, 0, index);
+                    let (x, y) = (index % width, index / width);
+                    let offset = Vec2::new(rng::random(), rng::random());
+                    let uv = Vec2::new(
+                        (offset.x() + x as f32) / width as f32,
+                        (offset.y() + y as f32) / height as f32,
+                    );
+                    camera.get_ray(uv)
+                })
+                .collect();
+            let mut paths = PathQueue::default();
+            for (pixel, ray) in camera_rays.iter().enumerate() {
+                paths.push(ray, Vec3::one(), None, pixel);
+            }
+            rays.camera += pixels as u64;
+
+            // the rays in `paths` lead to the vertex `bounce` of their path
+            for bounce in 0..=settings.depth {
+                if paths.len() == 0 {
+                    break;
+                }
+                if self.sort && bounce > 0 {
+                    let order =
+                        sorted((0..paths.len()).collect(), |index| octant(paths.dir[index]));
+                    paths = paths.reordered(&order);
+                }
+
+                // extend
+                let (hits, visits): (Vec<Option<Hit>>, Vec<u64>) = (0..paths.len())
+                    .into_par_iter()
+                    .map(|index| {
+                        reseed(bounce, 1, paths.pixel[index]);
+                        let visits = bvh::node_visits();
+                        let hit = world.hit(&paths.ray(index), RANGE);
+                        (hit, bvh::node_visits() - visits)
+                    })
+                    .unzip();
+                rays.node_visits += visits.iter().sum::<u64>();
+                if bounce > 0 {
+                    rays.bounce += paths.len() as u64;
+                }
+
+                if bounce == 0 {
+                    for (index, hit) in hits.iter().enumerate() {
+                        if let Some(hit) = hit {
+                            let pixel = paths.pixel[index];
+                            let first_hit = hit_counts[pixel] == 0;
+                            for (buffer, &aov) in aovs.iter_mut().zip(&settings.aovs) {
+                                buffer[pixel] += trace::hit_aov(aov, hit, camera, first_hit);
+                            }
+                            hit_counts[pixel] += 1;
+                        }
+                    }
+                }
+
+                // light from the environment is counted by the vertex the ray left from
+                let escaped: Vec<Option<Vec3>> = (0..paths.len())
+                    .into_par_iter()
+                    .map(|index| match hits[index] {
+                        Some(_) => None,
+                        None => {
+                            let ray = paths.ray(index);
+                            let radiance = trace::escaped(&ray, scene, paths.bsdf_pdf[index]);
+                            Some(paths.throughput[index] * radiance)
+                        }
+                    })
+                    .collect();
+                for (index, radiance) in escaped.into_iter().enumerate() {
+                    if let Some(radiance) = radiance {
+                        film.add(paths.pixel[index], bounce <= 1, radiance);
+                    }
+                }
+                if bounce == settings.depth {
+                    break;
+                }
+
+                let mut order: Vec<usize> =
+                    (0..paths.len()).filter(|&i| hits[i].is_some()).collect();
+                if self.sort {
+                    order = sorted(order, |index| hits[index].as_ref().unwrap().mat.id());
+                }
+
+                // shade
+                let shaded: Vec<Shaded> = order
+                    .par_iter()
+                    .map(|&index| {
+                        reseed(bounce, 2, paths.pixel[index]);
+                        let (r, hit) = (paths.ray(index), hits[index].as_ref().unwrap());
+                        shade(scene, settings, bounce, paths.throughput[index], &r, hit)
+                    })
+                    .collect();
+                let mut next = PathQueue::default();
+                let mut shadows = ShadowQueue::default();
+                for (&index, shaded) in order.iter().zip(shaded) {
+                    let (hit, pixel) = (hits[index].as_ref().unwrap(), paths.pixel[index]);
+                    let throughput = paths.throughput[index];
+                    for connection in shaded.connections {
+                        shadows.origin.push(hit.pos);
+                        shadows.dir.push(connection.dir);
+                        shadows.time.push(paths.time[index]);
+                        shadows.distance.push(connection.distance);
+                        shadows.radiance.push(throughput * connection.radiance);
+                        shadows.pixel.push(pixel);
+                        shadows.direct.push(bounce == 0);
+                    }
+                    if let Some((ray, attenuation, pdf)) = shaded.next {
+                        next.push(&ray, throughput * attenuation, pdf, pixel);
+                    }
+                }
+
+                // connect
+                let (transmittance, visits): (Vec<f32>, Vec<u64>) = (0..shadows.pixel.len())
+                    .into_par_iter()
+                    .map(|index| {
+                        reseed(bounce, 3, index);
+                        let ray = Ray::at_time(
+                            shadows.origin[index],
+                            shadows.dir[index],
+                            shadows.time[index],
+                        );
+                        let visits = bvh::node_visits();
+                        let transmittance =
+                            world.transmittance(&ray, [RANGE[0], shadows.distance[index]]);
+                        (transmittance, bvh::node_visits() - visits)
+                    })
+                    .unzip();
+                rays.node_visits += visits.iter().sum::<u64>();
+                rays.shadow += transmittance.len() as u64;
+                for (index, transmittance) in transmittance.into_iter().enumerate() {
+                    let radiance = shadows.radiance[index] * transmittance;
+                    film.add(shadows.pixel[index], shadows.direct[index], radiance);
+                }
+
+                paths = next;
+            }
+        }
+
+        let samples = settings.samples;
+        let mut frame = Frame {
+            dimensions: settings.dimensions,
+            color: (0..pixels)
+                .map(|pixel| (film.direct[pixel] + film.indirect[pixel]) / samples as f32)
+                .collect(),
+            aovs: Vec::new(),
+            rays,
+        };
+        for (buffer, &aov) in aovs.iter_mut().zip(&settings.aovs) {
+            for (pixel, value) in buffer.iter_mut().enumerate() {
+                let sum = match aov {
+                    Aov::Direct => film.direct[pixel],
+                    Aov::Indirect => film.indirect[pixel],
+                    _ => *value,
+                };
+                *value = trace::average_aov(aov, sum, samples, hit_counts[pixel]);
+            }
+            frame.aovs.push((aov, std::mem::take(buffer)));
+        }
+        frame
+    }
+}
+
+/// Scatters `r` off `hit` and samples the lights and environment from there, which is
+/// `trace::shade` up to where it would trace rays. `hit` is the vertex `bounce` of a path
+/// whose attenuation up to it is `throughput`.
+fn shade(
+    scene: &Scene,
+    settings: &RenderSettings,
+    bounce: i32,
+    throughput: Vec3,
+    r: &Ray,
+    hit: &Hit,
+) -> Shaded {
+    let (attenuation, scattered) = hit.mat.scatter(r, hit);
+    let ray = match scattered {
+        Some(ray) => ray,
+        None => return Shaded::default(),
+    };
+
+    let pdf = hit.mat.eval(hit, ray.dir).map(|(_, pdf)| pdf);
+    let mut connections = Vec::new();
+    if pdf.is_some() {
+        let u = Vec2::new(rng::random(), rng::random());
+        if let Some((dir, light_pdf)) = scene.environment.sample(u) {
+            if let Some((f, bsdf_pdf)) = hit.mat.eval(hit, dir) {
+                if f.max_element() > 0.0 {
+                    let weight = trace::power_heuristic(light_pdf, bsdf_pdf);
+                    connections.push(Connection {
+                        dir,
+                        distance: RANGE[1],
+                        radiance: f * scene.environment.radiance(dir) * weight / light_pdf,
+                    });
+                }
+            }
+        }
+
+        for light in &scene.lights {
+            if let Some((dir, distance, radiance)) = light.sample(hit.pos) {
+                if let Some((f, _)) = hit.mat.eval(hit, dir) {
+                    if f.max_element() > 0.0 {
+                        connections.push(Connection {
+                            dir,
+                            distance,
+                            radiance: f * radiance,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    Shaded {
+        next: trace::roulette(settings, bounce, throughput * attenuation)
+            .map(|survival| (ray, attenuation / survival, pdf)),
+        connections,
+    }
+}
+
+/// `order` sorted by `key`, which is only computed once per index.
+fn sorted(order: Vec<usize>, key: impl Fn(usize) -> u32 + Sync) -> Vec<usize> {
+    let mut keyed: Vec<(u32, usize)> = order
+        .into_par_iter()
+        .map(|index| (key(index), index))
+        .collect();
+    keyed.par_sort_unstable();
+    keyed.into_iter().map(|(_, index)| index).collect()
+}
+
+/// Which of the eight octants a direction points into.
+fn octant(dir: Vec3) -> u32 {
+    (dir.x() < 0.0) as u32 | ((dir.y() < 0.0) as u32) << 1 | ((dir.z() < 0.0) as u32) << 2
+}
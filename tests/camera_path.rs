@@ -0,0 +1,40 @@
+//! Loading recorded camera paths.
+
+use std::fs;
+use std::io::ErrorKind;
+use tracer::camera_path::CameraPath;
+
+fn load(name: &str, text: &str) -> std::io::Result<CameraPath> {
+    let path = std::env::temp_dir().join(format!("tracer-{}.path", name));
+    fs::write(&path, text).unwrap();
+    let loaded = CameraPath::load(&path.to_string_lossy());
+    fs::remove_file(&path).unwrap();
+    loaded
+}
+
+#[test]
+fn keyframes_are_sorted_by_time() {
+    let path = load(
+        "sorted",
+        "# time x y z yaw pitch roll\n1 0 0 0 90 0 0\n0 1 2 3 0 0 0\n",
+    )
+    .unwrap();
+    let times: Vec<f32> = path
+        .keyframes
+        .iter()
+        .map(|keyframe| keyframe.time)
+        .collect();
+    assert_eq!(times, vec![0.0, 1.0]);
+    assert_eq!(path.duration(), 1.0);
+}
+
+#[test]
+fn non_finite_values_are_errors() {
+    for line in &["NaN 0 0 0 0 0 0", "inf 0 0 0 0 0 0", "0 0 -inf 0 0 0 0"] {
+        let text = format!("0 0 0 0 0 0 0\n{}\n", line);
+        let error = load("non-finite", &text)
+            .err()
+            .expect("load a non-finite keyframe");
+        assert_eq!(error.kind(), ErrorKind::InvalidData, "{}", line);
+    }
+}
@@ -0,0 +1,70 @@
+//! Helpers shared by the integration tests.
+
+use glam::Vec3;
+use tracer::camera::Camera;
+use tracer::trace::RenderSettings;
+
+/// Pinhole camera, focused on what it looks at.
+pub fn camera(origin: Vec3, lookat: Vec3, fov: f32, aspect: f32) -> Camera {
+    Camera::new(
+        origin,
+        lookat,
+        Vec3::unit_y(),
+        fov,
+        aspect,
+        0.0,
+        (lookat - origin).length(),
+    )
+}
+
+/// Seeded, like every test render, so a failure repeats until the code changes.
+pub fn settings(
+    dimensions: (usize, usize),
+    samples: i32,
+    depth: i32,
+    roulette: Option<i32>,
+) -> RenderSettings {
+    RenderSettings {
+        dimensions,
+        samples,
+        depth,
+        roulette,
+        seed: Some(1),
+        aovs: Vec::new(),
+    }
+}
+
+/// Mean color over blocks of `size` pixels, which is what should agree between renders
+/// of the same image once the noise averages out.
+pub fn blocks(color: &[Vec3], dimensions: (usize, usize), size: usize) -> Vec<Vec3> {
+    let (width, height) = dimensions;
+    let mut means = Vec::new();
+    for block_y in 0..height / size {
+        for block_x in 0..width / size {
+            let mut sum = Vec3::zero();
+            for y in block_y * size..(block_y + 1) * size {
+                for x in block_x * size..(block_x + 1) * size {
+                    sum += color[x + y * width];
+                }
+            }
+            means.push(sum / (size * size) as f32);
+        }
+    }
+    means
+}
+
+/// Checks that two renders agree within 5% over blocks of 8x8 pixels.
+pub fn assert_same_image(color: &[Vec3], reference: &[Vec3], dimensions: (usize, usize)) {
+    let (color, reference) = (
+        blocks(color, dimensions, 8),
+        blocks(reference, dimensions, 8),
+    );
+    for (color, reference) in color.iter().zip(&reference) {
+        assert!(
+            (*color - *reference).length() < 0.05 * reference.length().max(0.1),
+            "{:?} {:?}",
+            color,
+            reference
+        );
+    }
+}
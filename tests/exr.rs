@@ -0,0 +1,124 @@
+//! OpenEXR files written by `exr::write` read back the same, and broken ones are errors
+//! rather than panics.
+
+use std::fs;
+use tracer::exr;
+
+const WIDTH: usize = 5;
+const HEIGHT: usize = 3;
+
+fn channel(offset: f32) -> Vec<f32> {
+    (0..WIDTH * HEIGHT)
+        .map(|i| offset + i as f32 * 0.25)
+        .collect()
+}
+
+/// Bytes of an image with R, G, B and one more channel, written through a file named
+/// after the test.
+fn written(name: &str) -> (String, Vec<u8>) {
+    let path = std::env::temp_dir()
+        .join(format!("tracer-{}.exr", name))
+        .to_string_lossy()
+        .into_owned();
+    let channels = vec![
+        ("R".to_string(), channel(0.0)),
+        ("G".to_string(), channel(10.0)),
+        ("B".to_string(), channel(-5.0)),
+        ("depth".to_string(), channel(100.0)),
+    ];
+    exr::write(&path, WIDTH, HEIGHT, channels).unwrap();
+    let bytes = fs::read(&path).unwrap();
+    (path, bytes)
+}
+
+/// Where the value of the attribute `name` starts, after its name, type and size.
+fn attribute(bytes: &[u8], name: &str, kind: &str) -> usize {
+    let tag = [name.as_bytes(), &[0], kind.as_bytes(), &[0]].concat();
+    let start = bytes
+        .windows(tag.len())
+        .position(|window| window == &tag[..])
+        .unwrap();
+    start + tag.len() + 4
+}
+
+/// End of the header, where the offset table starts.
+fn header_end(bytes: &[u8]) -> usize {
+    // the last attribute is a float, followed by the null byte ending the header
+    attribute(bytes, "screenWindowWidth", "float") + 4 + 1
+}
+
+fn assert_invalid(path: &str, bytes: &[u8]) {
+    fs::write(path, bytes).unwrap();
+    let error = exr::read_rgb(path).expect_err("read a broken file");
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{}", error);
+}
+
+#[test]
+fn reads_back_what_it_writes() {
+    let (path, _) = written("round-trip");
+    let (width, height, pixels) = exr::read_rgb(&path).unwrap();
+    assert_eq!((width, height), (WIDTH, HEIGHT));
+    for (i, pixel) in pixels.iter().enumerate() {
+        let value = i as f32 * 0.25;
+        assert_eq!(pixel.x(), value);
+        assert_eq!(pixel.y(), 10.0 + value);
+        assert_eq!(pixel.z(), -5.0 + value);
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn truncated_files_are_errors() {
+    let (path, bytes) = written("truncated");
+    for &length in &[0, 3, 8, 40, header_end(&bytes) + 4, bytes.len() - 1] {
+        assert_invalid(&path, &bytes[..length]);
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn corrupt_headers_are_errors() {
+    let (path, bytes) = written("corrupt-header");
+
+    // a compression attribute without a value
+    let at = attribute(&bytes, "compression", "compression");
+    let mut broken = bytes.clone();
+    broken[at - 4..at].copy_from_slice(&0u32.to_le_bytes());
+    broken.remove(at);
+    assert_invalid(&path, &broken);
+
+    // a data window with its corners swapped
+    let at = attribute(&bytes, "dataWindow", "box2i");
+    let mut broken = bytes.clone();
+    broken[at + 8..at + 12].copy_from_slice(&(-3i32).to_le_bytes());
+    assert_invalid(&path, &broken);
+
+    // one too big for the file to hold
+    let mut broken = bytes.clone();
+    broken[at + 8..at + 12].copy_from_slice(&i32::MAX.to_le_bytes());
+    assert_invalid(&path, &broken);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn chunks_outside_the_data_window_are_errors() {
+    let (path, bytes) = written("corrupt-chunk");
+    let table = header_end(&bytes);
+    let mut first_chunk = [0; 8];
+    first_chunk.copy_from_slice(&bytes[table..table + 8]);
+    let first_chunk = u64::from_le_bytes(first_chunk) as usize;
+
+    for &y in &[-1, HEIGHT as i32, i32::MAX, i32::MIN] {
+        let mut broken = bytes.clone();
+        broken[first_chunk..first_chunk + 4].copy_from_slice(&y.to_le_bytes());
+        assert_invalid(&path, &broken);
+    }
+
+    // and chunks past the end of the file
+    let mut broken = bytes.clone();
+    broken[table..table + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert_invalid(&path, &broken);
+
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,177 @@
+//! Renders every scene in `tests/golden` with a fixed seed and compares it against the
+//! image of the same name next to it. Failures leave the render and an amplified
+//! difference in `target/golden`.
+//!
+//! ```text
+//! cargo test --test golden                 compare against the goldens
+//! cargo test --test golden -- --bless      replace the goldens with what renders now
+//! cargo test --test golden -- sky          only the scenes with "sky" in their name
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracer::scene_file;
+use tracer::trace::{self, RenderSettings};
+
+const DIMENSIONS: (usize, usize) = (64, 48);
+
+/// Root mean square error over the 8 bit channels, in [0, 1], that still passes. Renders
+/// are the same on a machine, this leaves room for math libraries that round differently.
+const TOLERANCE: f64 = 0.01;
+
+/// How much brighter the difference images are than the difference itself.
+const DIFF_SCALE: f64 = 8.0;
+
+fn settings() -> RenderSettings {
+    RenderSettings {
+        dimensions: DIMENSIONS,
+        samples: 16,
+        depth: 8,
+        roulette: Some(3),
+        seed: Some(1),
+        aovs: Vec::new(),
+    }
+}
+
+fn render(scene_path: &Path) -> io::Result<Vec<u8>> {
+    let (scene, mut camera) = scene_file::load(&scene_path.to_string_lossy())?;
+    camera.aspect = DIMENSIONS.0 as f32 / DIMENSIONS.1 as f32;
+    let pixels = trace::to_rgba8(&trace::render(&camera, &scene, &settings()).color);
+
+    // the bottom row comes first, images start at the top
+    Ok(pixels
+        .chunks(DIMENSIONS.0 * 4)
+        .rev()
+        .flatten()
+        .cloned()
+        .collect())
+}
+
+fn save(path: &Path, pixels: &[u8]) -> io::Result<()> {
+    let (width, height) = (DIMENSIONS.0 as u32, DIMENSIONS.1 as u32);
+    image::save_buffer(path, pixels, width, height, image::RGBA(8))
+}
+
+fn load(path: &Path) -> io::Result<Vec<u8>> {
+    let image = image::open(path)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?
+        .to_rgba();
+    if image.dimensions() != (DIMENSIONS.0 as u32, DIMENSIONS.1 as u32) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "golden is {:?}, renders are {:?}",
+                image.dimensions(),
+                DIMENSIONS
+            ),
+        ));
+    }
+    Ok(image.into_raw())
+}
+
+/// Root mean square error between the color channels of two RGBA images.
+fn rmse(a: &[u8], b: &[u8]) -> f64 {
+    let mut sum = 0.0;
+    for (a, b) in a.chunks(4).zip(b.chunks(4)) {
+        for channel in 0..3 {
+            let difference = (f64::from(a[channel]) - f64::from(b[channel])) / 255.0;
+            sum += difference * difference;
+        }
+    }
+    (sum / (3 * a.len() / 4) as f64).sqrt()
+}
+
+/// Per channel difference, scaled up so small ones show.
+fn diff_image(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.chunks(4)
+        .zip(b.chunks(4))
+        .flat_map(|(a, b)| {
+            let channel = |i: usize| {
+                let difference = (f64::from(a[i]) - f64::from(b[i])).abs();
+                (difference * DIFF_SCALE).min(255.0) as u8
+            };
+            vec![channel(0), channel(1), channel(2), 255]
+        })
+        .collect()
+}
+
+/// Compares the render of `scene_path` with its golden, or replaces the golden when
+/// blessing. Errors say why the scene failed.
+fn check(scene_path: &Path, bless: bool, failures_dir: &Path) -> io::Result<()> {
+    let pixels = render(scene_path)?;
+    let golden_path = scene_path.with_extension("png");
+    if bless {
+        return save(&golden_path, &pixels);
+    }
+
+    let golden = load(&golden_path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("{}: {}, run with --bless", golden_path.display(), e),
+        )
+    })?;
+    let error = rmse(&pixels, &golden);
+    if error <= TOLERANCE {
+        return Ok(());
+    }
+
+    let name = scene_path.file_stem().unwrap().to_string_lossy();
+    fs::create_dir_all(failures_dir)?;
+    let render_path = failures_dir.join(format!("{}.png", name));
+    save(&render_path, &pixels)?;
+    save(
+        &failures_dir.join(format!("{}-diff.png", name)),
+        &diff_image(&pixels, &golden),
+    )?;
+    Err(io::Error::other(format!(
+        "rmse {:.4} over {}, see {}",
+        error,
+        TOLERANCE,
+        render_path.display()
+    )))
+}
+
+fn main() {
+    let mut bless = false;
+    let mut filters = Vec::new();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--bless" => bless = true,
+            // the flags cargo passes to every test binary
+            flag if flag.starts_with("--") => {}
+            filter => filters.push(filter.to_string()),
+        }
+    }
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut scenes: Vec<PathBuf> = fs::read_dir(root.join("tests/golden"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "scene"))
+        .filter(|path| {
+            let name = path.file_stem().unwrap().to_string_lossy();
+            filters.is_empty() || filters.iter().any(|filter| name.contains(filter.as_str()))
+        })
+        .collect();
+    scenes.sort();
+
+    let failures_dir = root.join("target/golden");
+    let mut failures = 0;
+    for scene_path in &scenes {
+        let name = scene_path.file_stem().unwrap().to_string_lossy();
+        match check(scene_path, bless, &failures_dir) {
+            Ok(()) if bless => println!("golden {} ... blessed", name),
+            Ok(()) => println!("golden {} ... ok", name),
+            Err(error) => {
+                println!("golden {} ... FAILED: {}", name, error);
+                failures += 1;
+            }
+        }
+    }
+
+    println!("\n{} scenes, {} failed", scenes.len(), failures);
+    if failures > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,121 @@
+//! The compute shader can't run in CI, so the CPU tracer it mirrors is checked against
+//! values known in closed form, and the two are only compared on a machine with a GPU:
+//!
+//! ```text
+//! cargo test --test gpu -- --ignored
+//! ```
+
+mod common;
+
+use glam::Vec3;
+use std::sync::Arc;
+use tracer::camera::{Camera, Fisheye};
+use tracer::environment::{Constant, EnvironmentMap};
+use tracer::frame::Aov;
+use tracer::gpu::{Film, GpuTracer, PackedScene};
+use tracer::material::Material;
+use tracer::scene::{self, Scene};
+use tracer::sphere::Sphere;
+use tracer::trace::{self, RenderSettings};
+
+fn camera(aspect: f32) -> Camera {
+    common::camera(Vec3::new(0.0, 0.0, 2.0), Vec3::zero(), 40.0, aspect)
+}
+
+fn settings(dimensions: (usize, usize), samples: i32) -> RenderSettings {
+    common::settings(dimensions, samples, 8, None)
+}
+
+#[test]
+fn cpu_lambert_sphere_in_a_furnace() {
+    // every bounce off a convex diffuse object escapes, so it reflects exactly its albedo
+    let mut scene = Scene::new(vec![Box::new(Sphere::new(
+        Vec3::zero(),
+        0.5,
+        Material::Lambert {
+            albedo: Vec3::splat(0.5),
+        },
+    ))]);
+    scene.environment = Box::new(Constant { color: Vec3::one() });
+
+    let mut settings = settings((16, 16), 4);
+    settings.aovs = vec![Aov::Albedo];
+    let frame = trace::render(&camera(1.0), &scene, &settings);
+
+    // the albedo AOV is half the fraction of samples that hit, the rest see the furnace
+    let albedo = frame.aov(Aov::Albedo).unwrap();
+    for (color, albedo) in frame.color.iter().zip(albedo) {
+        assert!(
+            (*color - (Vec3::one() - *albedo)).length() < 1e-4,
+            "{:?}",
+            color
+        );
+    }
+    assert!(albedo.iter().any(|albedo| albedo.x() == 0.5));
+}
+
+#[test]
+fn packs_spheres_and_lights() {
+    let scene = scene::lights_scene();
+    let packed = PackedScene::new(&scene).unwrap();
+
+    assert_eq!(packed.sphere_count, scene.world.len());
+    assert_eq!(packed.spheres.len(), 3 * scene.world.len());
+    assert_eq!(packed.light_count, scene.lights.len());
+    assert_eq!(packed.lights.len(), 3 * scene.lights.len().max(1));
+    assert_eq!(packed.environment.len(), 2);
+}
+
+#[test]
+fn refuses_what_the_shader_cant_trace() {
+    assert!(PackedScene::new(&Scene::new(scene::cloud_world())).is_err());
+
+    let mut scene = Scene::new(scene::default_world());
+    scene.environment = Box::new(EnvironmentMap::new(1, 1, vec![Vec3::one()], 0.0));
+    assert!(PackedScene::new(&scene).is_err());
+
+    assert!(Film::new(&camera(1.0)).is_ok());
+    let fisheye = camera(1.0).with_model(Arc::new(Fisheye { fov: 180.0 }));
+    assert!(Film::new(&fisheye).is_err());
+}
+
+#[test]
+#[ignore]
+fn gpu_matches_cpu() {
+    use glium::backend::glutin::glutin::dpi::PhysicalSize;
+    use glium::backend::glutin::glutin::{Api, ContextBuilder, EventsLoop, GlRequest};
+    use glium::HeadlessRenderer;
+
+    let events_loop = EventsLoop::new();
+    let context = ContextBuilder::new()
+        .with_gl(GlRequest::Specific(Api::OpenGl, (4, 3)))
+        .build_headless(&events_loop, PhysicalSize::new(1.0, 1.0))
+        .unwrap();
+    let renderer = HeadlessRenderer::new(context).unwrap();
+    let mut tracer = GpuTracer::new(&renderer).unwrap();
+
+    let scene = Scene::new(scene::default_world());
+    let dimensions = (48, 24);
+    let settings = settings(dimensions, 512);
+    let camera = camera(2.0);
+
+    let gpu = tracer.render(&camera, &scene, &settings).unwrap();
+    let cpu = trace::render(&camera, &scene, &settings);
+    assert_eq!(gpu.rays.camera, cpu.rays.camera);
+
+    common::assert_same_image(&gpu.color, &cpu.color, dimensions);
+
+    // roulette ends paths early without changing the mean
+    let mut settings = settings;
+    settings.roulette = Some(1);
+    let gpu = tracer.render(&camera, &scene, &settings).unwrap();
+    assert!(gpu.rays.bounce < cpu.rays.bounce);
+    common::assert_same_image(&gpu.color, &cpu.color, dimensions);
+
+    settings.seed = Some(7);
+    let first = tracer.render(&camera, &scene, &settings).unwrap();
+    assert_eq!(
+        tracer.render(&camera, &scene, &settings).unwrap().color,
+        first.color
+    );
+}
@@ -0,0 +1,44 @@
+//! Spot lights fade out between their inner and outer angles, and end at the outer angle
+//! whichever way round the two are given.
+
+use glam::Vec3;
+use tracer::light::Light;
+
+fn spot(inner_angle: f32, outer_angle: f32) -> Light {
+    Light::Spot {
+        position: Vec3::zero(),
+        direction: -Vec3::unit_y(),
+        intensity: Vec3::one(),
+        inner_angle,
+        outer_angle,
+    }
+}
+
+/// Radiance a unit away from the light, `angle` degrees off its axis.
+fn radiance(light: &Light, angle: f32) -> Option<f32> {
+    let angle = angle.to_radians();
+    let pos = Vec3::new(angle.sin(), -angle.cos(), 0.0);
+    light.sample(pos).map(|(_, _, radiance)| radiance.x())
+}
+
+#[test]
+fn spots_fade_between_their_angles() {
+    let light = spot(20.0, 30.0);
+    assert!((radiance(&light, 0.0).unwrap() - 1.0).abs() < 1e-5);
+    assert!((radiance(&light, 19.0).unwrap() - 1.0).abs() < 1e-5);
+    let fading = radiance(&light, 25.0).unwrap();
+    assert!(fading > 0.0 && fading < 1.0, "{}", fading);
+    assert_eq!(radiance(&light, 31.0), None);
+}
+
+#[test]
+fn spots_without_a_fade_have_a_hard_edge() {
+    for &inner in &[30.0, 45.0] {
+        let light = spot(inner, 30.0);
+        for &angle in &[0.0, 15.0, 29.0] {
+            let radiance = radiance(&light, angle).unwrap();
+            assert!((radiance - 1.0).abs() < 1e-5, "{} at {}", radiance, angle);
+        }
+        assert_eq!(radiance(&light, 31.0), None);
+    }
+}
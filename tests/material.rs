@@ -0,0 +1,45 @@
+//! Material ids end up in AOV files, so they have to stay the same from build to build.
+
+use glam::Vec3;
+use tracer::material::Material;
+
+#[test]
+fn ids_are_stable() {
+    let lambert = Material::Lambert {
+        albedo: Vec3::splat(0.5),
+    };
+    // FNV-1a over the kind, the albedo and the parameter bits, folded to 24 bits
+    assert_eq!(lambert.id(), 2_670_940);
+}
+
+#[test]
+fn ids_tell_materials_apart() {
+    let materials = [
+        Material::Lambert {
+            albedo: Vec3::splat(0.5),
+        },
+        Material::Lambert {
+            albedo: Vec3::new(0.5, 0.5, 0.6),
+        },
+        Material::Metal {
+            albedo: Vec3::splat(0.5),
+            fuzz: 0.0,
+        },
+        Material::Metal {
+            albedo: Vec3::splat(0.5),
+            fuzz: 0.1,
+        },
+        Material::Dielectric {
+            albedo: Vec3::one(),
+            ref_idx: 1.5,
+        },
+        Material::Isotropic {
+            albedo: Vec3::splat(0.5),
+        },
+    ];
+    let mut ids: Vec<u32> = materials.iter().map(|m| m.id()).collect();
+    assert!(ids.iter().all(|&id| id > 0 && id < 1 << 24));
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), materials.len());
+}
@@ -0,0 +1,61 @@
+//! Russian roulette ends paths early and boosts the ones that go on, which should leave
+//! the image the same on average while tracing fewer rays.
+
+mod common;
+
+use glam::Vec3;
+use tracer::camera::Camera;
+use tracer::scene::{self, Scene};
+use tracer::trace::{self, RenderSettings};
+use tracer::wavefront::Wavefront;
+
+const DIMENSIONS: (usize, usize) = (48, 24);
+
+fn camera() -> Camera {
+    common::camera(
+        Vec3::new(0.0, 0.5, 2.0),
+        Vec3::new(0.0, 0.0, -1.0),
+        60.0,
+        2.0,
+    )
+}
+
+fn settings(roulette: Option<i32>) -> RenderSettings {
+    common::settings(DIMENSIONS, 256, 50, roulette)
+}
+
+#[test]
+fn mean_image_is_unchanged() {
+    for scene in &[Scene::new(scene::default_world()), scene::lights_scene()] {
+        let every_path = trace::render(&camera(), scene, &settings(None));
+        // from the first bounce on, as that is the hardest on the noise
+        let roulette = trace::render(&camera(), scene, &settings(Some(0)));
+
+        common::assert_same_image(&roulette.color, &every_path.color, DIMENSIONS);
+        assert!(roulette.rays.bounce < every_path.rays.bounce);
+    }
+}
+
+#[test]
+fn wavefront_mean_image_is_unchanged() {
+    let scene = scene::lights_scene();
+    let every_path = Wavefront::default().render(&camera(), &scene, &settings(None));
+    let roulette = Wavefront::default().render(&camera(), &scene, &settings(Some(0)));
+
+    common::assert_same_image(&roulette.color, &every_path.color, DIMENSIONS);
+    assert!(roulette.rays.bounce < every_path.rays.bounce);
+}
+
+#[test]
+fn paths_go_on_until_the_minimum_bounces() {
+    let mut settings = settings(Some(2));
+    settings.depth = 2;
+    let scene = Scene::new(scene::default_world());
+    let with = trace::render(&camera(), &scene, &settings);
+    settings.roulette = None;
+    let without = trace::render(&camera(), &scene, &settings);
+
+    // paths reach the depth before roulette gets a say, so both trace alike
+    let ratio = with.rays.bounce as f32 / without.rays.bounce as f32;
+    assert!((ratio - 1.0).abs() < 0.02, "{}", ratio);
+}
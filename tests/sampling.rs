@@ -0,0 +1,881 @@
+//! Checks that the sampling routines and the materials' scatter distributions draw what
+//! they claim to, with chi-square goodness of fit tests, and that materials neither
+//! create energy nor depend on which way light goes through them.
+//!
+//! Every test seeds the generator, so a failure repeats until the code changes.
+
+use glam::{Vec2, Vec3};
+use std::f32::consts::PI;
+use tracer::aabb::Aabb;
+use tracer::camera::{Aperture, Camera};
+use tracer::distribution::{Distribution1D, Distribution2D};
+use tracer::environment::{Constant, Environment, EnvironmentMap};
+use tracer::hit::Hit;
+use tracer::material::Material;
+use tracer::math::{
+    orthonormal_basis, random_in_unit_disk, random_in_unit_sphere, random_unit_vector, schlick,
+};
+use tracer::ray::Ray;
+use tracer::rng;
+use tracer::scene::{Scene, World};
+use tracer::sky::Sky;
+use tracer::sphere::Sphere;
+use tracer::trace::{self, RenderSettings};
+use tracer::volume::{Volume, VoxelGrid};
+use tracer::wavefront::Wavefront;
+
+const SAMPLES: usize = 200_000;
+
+/// Standard normal quantile of the significance level, 1e-4. Tests are seeded so this
+/// doesn't trade flakiness for power, it only leaves room for rounding.
+const Z: f64 = 3.719;
+
+/// Upper quantile of the chi-square distribution with `dof` degrees of freedom, from the
+/// Wilson–Hilferty approximation.
+fn critical_value(dof: usize) -> f64 {
+    let k = dof as f64;
+    let a = 2.0 / (9.0 * k);
+    k * (1.0 - a + Z * a.sqrt()).powi(3)
+}
+
+/// Pearson's statistic of `observed` counts against the `expected` probability of each
+/// bin, with its degrees of freedom. Bins expecting fewer than 5 samples are pooled since
+/// the statistic isn't chi-square distributed for them.
+fn chi_square(observed: &[u64], expected: &[f64]) -> (f64, usize) {
+    let total: u64 = observed.iter().sum();
+    let mut bins: Vec<(f64, f64)> = observed
+        .iter()
+        .zip(expected)
+        .map(|(&o, &e)| (o as f64, e * total as f64))
+        .collect();
+    bins.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+    let (mut statistic, mut dof) = (0.0, 0);
+    let (mut pooled_observed, mut pooled_expected) = (0.0, 0.0);
+    for (observed, expected) in bins {
+        if expected == 0.0 {
+            assert!(
+                observed == 0.0,
+                "{} samples where none are expected",
+                observed
+            );
+            continue;
+        }
+        if pooled_expected + expected < 5.0 {
+            pooled_observed += observed;
+            pooled_expected += expected;
+            continue;
+        }
+        let (o, e) = (observed + pooled_observed, expected + pooled_expected);
+        statistic += (o - e) * (o - e) / e;
+        dof += 1;
+        pooled_observed = 0.0;
+        pooled_expected = 0.0;
+    }
+    (statistic, dof.max(2) - 1)
+}
+
+fn assert_fits(name: &str, observed: &[u64], expected: &[f64]) {
+    let sum: f64 = expected.iter().sum();
+    assert!(
+        (sum - 1.0).abs() < 1e-3,
+        "{}: expected probabilities add up to {}",
+        name,
+        sum
+    );
+    let (statistic, dof) = chi_square(observed, expected);
+    let critical = critical_value(dof);
+    assert!(
+        statistic <= critical,
+        "{}: chi-square {:.1} over {:.1} with {} degrees of freedom",
+        name,
+        statistic,
+        critical,
+        dof
+    );
+}
+
+const COS_BINS: usize = 16;
+const PHI_BINS: usize = 16;
+
+/// Cell of a direction on a grid in (cos θ, φ) around `axis`, cells of which cover the
+/// same solid angle.
+fn direction_bin(dir: Vec3, axis: Vec3) -> usize {
+    let dir = dir.normalize();
+    let (t, b) = orthonormal_basis(axis);
+    let cos = Vec3::dot(dir, axis);
+    let phi = Vec3::dot(dir, b).atan2(Vec3::dot(dir, t)) + PI;
+    let i = ((cos + 1.0) / 2.0 * COS_BINS as f32) as usize;
+    let j = (phi / (2.0 * PI) * PHI_BINS as f32) as usize;
+    i.min(COS_BINS - 1) * PHI_BINS + j.min(PHI_BINS - 1)
+}
+
+/// Probability a solid angle `pdf` puts in each cell of `direction_bin`, integrated
+/// with the midpoint rule.
+fn direction_probabilities(axis: Vec3, pdf: impl Fn(Vec3) -> f32) -> Vec<f64> {
+    const STEPS: usize = 8;
+    let (t, b) = orthonormal_basis(axis);
+    let (d_cos, d_phi) = (
+        2.0 / (COS_BINS * STEPS) as f64,
+        2.0 * f64::from(PI) / (PHI_BINS * STEPS) as f64,
+    );
+
+    let mut probabilities = vec![0.0; COS_BINS * PHI_BINS];
+    for i in 0..COS_BINS * STEPS {
+        let cos = -1.0 + (i as f64 + 0.5) * d_cos;
+        let sin = (1.0 - cos * cos).sqrt();
+        for j in 0..PHI_BINS * STEPS {
+            let phi = (j as f64 + 0.5) * d_phi - f64::from(PI);
+            let dir =
+                (sin * phi.cos()) as f32 * t + (sin * phi.sin()) as f32 * b + cos as f32 * axis;
+            let bin = (i / STEPS) * PHI_BINS + j / STEPS;
+            probabilities[bin] += f64::from(pdf(dir)) * d_cos * d_phi;
+        }
+    }
+    probabilities
+}
+
+/// Fits `SAMPLES` directions from `sample` to the solid angle density `pdf`. Samples
+/// that come back empty count as lost, which `pdf` accounts for by integrating to less
+/// than one.
+fn assert_directions(
+    name: &str,
+    axis: Vec3,
+    mut sample: impl FnMut() -> Option<Vec3>,
+    pdf: impl Fn(Vec3) -> f32,
+) {
+    let mut observed = vec![0; COS_BINS * PHI_BINS + 1];
+    for _ in 0..SAMPLES {
+        match sample() {
+            Some(dir) => observed[direction_bin(dir, axis)] += 1,
+            None => observed[COS_BINS * PHI_BINS] += 1,
+        }
+    }
+    let mut expected = direction_probabilities(axis, pdf);
+    let lost = 1.0 - expected.iter().sum::<f64>();
+    expected.push(lost.max(0.0));
+    assert_fits(name, &observed, &expected);
+}
+
+fn hit(normal: Vec3, mat: Material) -> Hit {
+    Hit {
+        t: 1.0,
+        pos: Vec3::zero(),
+        normal,
+        mat,
+        object: 0,
+    }
+}
+
+/// Ray arriving at the origin from `from`.
+fn incoming(from: Vec3) -> Ray {
+    Ray::at_time(from, -from, 0.0)
+}
+
+fn tilted_normal() -> Vec3 {
+    Vec3::new(0.3, 0.8, -0.5).normalize()
+}
+
+#[test]
+fn unit_vectors_are_uniform_directions() {
+    rng::seed(&[1]);
+    assert_directions(
+        "random_unit_vector",
+        Vec3::unit_z(),
+        || Some(random_unit_vector()),
+        |_| 1.0 / (4.0 * PI),
+    );
+    for _ in 0..1000 {
+        assert!((random_unit_vector().length() - 1.0).abs() < 1e-5);
+    }
+}
+
+#[test]
+fn unit_sphere_samples_fill_the_ball() {
+    rng::seed(&[2]);
+    // a uniform point in the ball has a uniform direction and r³ uniform in [0, 1]
+    const RADIUS_BINS: usize = 8;
+    let directions = 4 * 4;
+    let mut observed = vec![0; RADIUS_BINS * directions];
+    for _ in 0..SAMPLES {
+        let p = random_in_unit_sphere();
+        let r = p.length();
+        assert!(r <= 1.0 + 1e-5);
+        let shell = ((r * r * r * RADIUS_BINS as f32) as usize).min(RADIUS_BINS - 1);
+        let octant = (p.x() > 0.0) as usize + 2 * (p.y() > 0.0) as usize;
+        let band = ((p.z() / r.max(1e-9) + 1.0) * 2.0).clamp(0.0, 3.99) as usize;
+        observed[shell * directions + octant * 4 + band] += 1;
+    }
+    let expected = vec![1.0 / observed.len() as f64; observed.len()];
+    assert_fits("random_in_unit_sphere", &observed, &expected);
+}
+
+#[test]
+fn unit_disk_samples_fill_the_disk() {
+    rng::seed(&[3]);
+    // a uniform point in the disk has a uniform angle and r² uniform in [0, 1]
+    const RADIUS_BINS: usize = 10;
+    const ANGLE_BINS: usize = 16;
+    let mut observed = vec![0; RADIUS_BINS * ANGLE_BINS];
+    for _ in 0..SAMPLES {
+        let p = random_in_unit_disk();
+        let r2 = p.length_squared();
+        assert!(r2 <= 1.0 + 1e-5);
+        let ring = ((r2 * RADIUS_BINS as f32) as usize).min(RADIUS_BINS - 1);
+        let angle = (p.y().atan2(p.x()) + PI) / (2.0 * PI);
+        let sector = ((angle * ANGLE_BINS as f32) as usize).min(ANGLE_BINS - 1);
+        observed[ring * ANGLE_BINS + sector] += 1;
+    }
+    let expected = vec![1.0 / observed.len() as f64; observed.len()];
+    assert_fits("random_in_unit_disk", &observed, &expected);
+}
+
+#[test]
+fn polygon_apertures_are_sampled_uniformly() {
+    rng::seed(&[4]);
+    let mut camera = Camera::new(
+        Vec3::zero(),
+        -Vec3::unit_z(),
+        Vec3::unit_y(),
+        60.0,
+        1.0,
+        1.0,
+        1.0,
+    );
+    let (blades, rotation) = (6, 15.0);
+    camera.aperture = Aperture::Polygon { blades, rotation };
+
+    const CELLS: usize = 12;
+    let cell = |p: Vec2| {
+        let index = |x: f32| (((x + 1.0) / 2.0 * CELLS as f32) as usize).min(CELLS - 1);
+        index(p.y()) * CELLS + index(p.x())
+    };
+    let mut observed = vec![0; CELLS * CELLS];
+    for _ in 0..SAMPLES {
+        observed[cell(camera.sample_aperture())] += 1;
+    }
+
+    // area of the polygon in each cell, the polygon being where a point is inside of
+    // every edge
+    let corner = |i: u32| {
+        let angle = rotation.to_radians() + 2.0 * PI * i as f32 / blades as f32;
+        Vec2::new(angle.cos(), angle.sin())
+    };
+    let inside = |p: Vec2| {
+        (0..blades).all(|i| {
+            let (a, b) = (corner(i), corner(i + 1));
+            let (edge, to_p) = (b - a, p - a);
+            edge.x() * to_p.y() - edge.y() * to_p.x() >= 0.0
+        })
+    };
+    const STEPS: usize = 64;
+    let mut expected = vec![0.0; CELLS * CELLS];
+    for y in 0..CELLS * STEPS {
+        for x in 0..CELLS * STEPS {
+            let p = Vec2::new(x as f32 + 0.5, y as f32 + 0.5) / (CELLS * STEPS) as f32 * 2.0
+                - Vec2::one();
+            if inside(p) {
+                expected[cell(p)] += 1.0;
+            }
+        }
+    }
+    let area: f64 = expected.iter().sum();
+    for e in &mut expected {
+        *e /= area;
+    }
+    assert_fits("polygon aperture", &observed, &expected);
+}
+
+#[test]
+fn cat_eye_samples_are_inside_the_aperture_and_the_barrel() {
+    rng::seed(&[12]);
+    // a lens radius of 1 leaves the samples at the scale `sample_lens` draws them
+    let aspect = 2.0;
+    let mut camera = Camera::new(
+        Vec3::zero(),
+        -Vec3::unit_z(),
+        Vec3::unit_y(),
+        60.0,
+        aspect,
+        2.0,
+        1.0,
+    );
+    camera.cat_eye = 1.0;
+
+    for &(x, y) in &[(0.5, 0.5), (0.75, 0.5), (1.0, 0.5), (1.0, 1.0), (0.0, 0.0)] {
+        let film = Vec2::new((2.0 * x - 1.0) * aspect, 2.0 * y - 1.0);
+        // the barrel is shifted away from the frame edge by at most the aperture radius
+        let barrel_center = -film / film.length().max(1.0);
+        for _ in 0..10_000 {
+            let ray = camera.get_ray(Vec2::new(x, y));
+            let lens = Vec2::new(ray.origin.x(), ray.origin.y());
+            assert!(
+                lens.length() <= 1.0 + 1e-5,
+                "{:?} outside the aperture",
+                lens
+            );
+            assert!(
+                (lens - barrel_center).length() <= 1.0 + 1e-5,
+                "{:?} outside the barrel at {:?}",
+                lens,
+                (x, y)
+            );
+        }
+    }
+}
+
+#[test]
+fn distribution_1d_follows_its_function() {
+    rng::seed(&[5]);
+    let func = vec![1.0, 0.0, 3.0, 2.0, 0.5, 0.0, 0.0, 4.0];
+    let distribution = Distribution1D::new(func.clone());
+    let sum: f32 = func.iter().sum();
+
+    // two bins per bucket, samples are uniform within one
+    let bins = 2 * func.len();
+    let mut observed = vec![0; bins];
+    for _ in 0..SAMPLES {
+        let (x, pdf, index) = distribution.sample(rng::random());
+        assert_eq!(index, (x * func.len() as f32) as usize);
+        assert!((pdf - distribution.pdf(x)).abs() < 1e-5);
+        assert!((pdf - func[index] * func.len() as f32 / sum).abs() < 1e-5);
+        observed[((x * bins as f32) as usize).min(bins - 1)] += 1;
+    }
+    let expected: Vec<f64> = (0..bins)
+        .map(|i| f64::from(func[i / 2] / sum) / 2.0)
+        .collect();
+    assert_fits("Distribution1D", &observed, &expected);
+}
+
+#[test]
+fn distribution_2d_follows_its_function() {
+    rng::seed(&[6]);
+    let (width, height) = (6, 4);
+    let func: Vec<f32> = (0..width * height)
+        .map(|i| ((i * 7) % 5) as f32 * ((i / width) as f32 + 0.5))
+        .collect();
+    let distribution = Distribution2D::new(&func, width);
+    let sum: f32 = func.iter().sum();
+
+    let mut observed = vec![0; width * height];
+    for _ in 0..SAMPLES {
+        let (p, pdf) = distribution.sample(Vec2::new(rng::random(), rng::random()));
+        let (x, y) = (
+            (p.x() * width as f32) as usize,
+            (p.y() * height as f32) as usize,
+        );
+        let index = y.min(height - 1) * width + x.min(width - 1);
+        assert!((pdf - distribution.pdf(p)).abs() < 1e-4);
+        assert!((pdf - func[index] * (width * height) as f32 / sum).abs() < 1e-4);
+        observed[index] += 1;
+    }
+    let expected: Vec<f64> = func.iter().map(|&f| f64::from(f / sum)).collect();
+    assert_fits("Distribution2D", &observed, &expected);
+}
+
+#[test]
+fn environment_maps_are_sampled_by_luminance() {
+    rng::seed(&[7]);
+    let (width, height) = (8, 4);
+    let texels: Vec<Vec3> = (0..width * height)
+        .map(|i| Vec3::splat(0.1 + ((i * 5) % 7) as f32))
+        .collect();
+    let map = EnvironmentMap::new(width, height, texels, 0.0);
+
+    // binned by texel, where the probability of each follows its luminance times the
+    // solid angle it covers
+    let texel = |dir: Vec3| {
+        let u = (dir.x().atan2(-dir.z()) / (2.0 * PI)).rem_euclid(1.0);
+        let v = dir.y().clamp(-1.0, 1.0).acos() / PI;
+        let (x, y) = ((u * width as f32) as usize, (v * height as f32) as usize);
+        y.min(height - 1) * width + x.min(width - 1)
+    };
+    let (mut observed, mut mismatches) = (vec![0; width * height], 0);
+    for _ in 0..SAMPLES {
+        let (dir, pdf) = map.sample(Vec2::new(rng::random(), rng::random())).unwrap();
+        if (pdf - map.pdf(dir)).abs() > 0.05 * pdf {
+            mismatches += 1;
+        }
+        observed[texel(dir)] += 1;
+    }
+    // rounding can move a sample at the edge of a texel into its neighbour, and the pdf
+    // changes quickly next to the poles
+    assert!(mismatches < SAMPLES / 1000, "{} pdfs disagree", mismatches);
+
+    const STEPS: usize = 32;
+    let (d_theta, d_phi) = (
+        f64::from(PI) / (height * STEPS) as f64,
+        2.0 * f64::from(PI) / (width * STEPS) as f64,
+    );
+    let mut expected = vec![0.0; width * height];
+    for i in 0..height * STEPS {
+        let theta = (i as f64 + 0.5) * d_theta;
+        for j in 0..width * STEPS {
+            let phi = (j as f64 + 0.5) * d_phi;
+            let dir = Vec3::new(
+                (theta.sin() * phi.sin()) as f32,
+                theta.cos() as f32,
+                (-theta.sin() * phi.cos()) as f32,
+            );
+            let solid_angle = theta.sin() * d_theta * d_phi;
+            expected[(i / STEPS) * width + j / STEPS] += f64::from(map.pdf(dir)) * solid_angle;
+        }
+    }
+    assert_fits("EnvironmentMap", &observed, &expected);
+}
+
+#[test]
+fn the_sun_is_sampled_uniformly_over_its_disc() {
+    rng::seed(&[8]);
+    let sky = Sky::new(30.0, 40.0, 3.0);
+    let (sun, (t, b)) = (sky.sun_dir(), orthonormal_basis(sky.sun_dir()));
+    let cos_radius = tracer::sky::SUN_ANGULAR_RADIUS.cos();
+    let sin2_radius = tracer::sky::SUN_ANGULAR_RADIUS.sin().powi(2);
+
+    // the disc is too small for a grid over the sphere, so the cone is binned by
+    // solid angle from its axis and by angle around it
+    const RINGS: usize = 8;
+    const SECTORS: usize = 8;
+    let mut observed = vec![0; RINGS * SECTORS];
+    for _ in 0..SAMPLES {
+        let (dir, pdf) = sky.sample(Vec2::new(rng::random(), rng::random())).unwrap();
+        assert!((dir.length() - 1.0).abs() < 1e-4);
+        // right at the edge of the disc rounding can put a sample outside of it
+        if Vec3::dot(dir, sun) > cos_radius + 1e-6 {
+            assert!((pdf - sky.pdf(dir)).abs() <= 1e-3 * pdf);
+        }
+
+        // sin² θ is close enough to 1 - cos θ over the disc, and precise in f32
+        let sin2 = Vec3::cross(dir, sun).length_squared() / sin2_radius;
+        let ring = (sin2 * RINGS as f32) as usize;
+        let angle = (Vec3::dot(dir, b).atan2(Vec3::dot(dir, t)) + PI) / (2.0 * PI);
+        let sector = ((angle * SECTORS as f32) as usize).min(SECTORS - 1);
+        observed[ring.min(RINGS - 1) * SECTORS + sector] += 1;
+    }
+    let expected = vec![1.0 / observed.len() as f64; observed.len()];
+    assert_fits("Sky", &observed, &expected);
+
+    assert_eq!(sky.pdf(Vec3::unit_y()), 0.0);
+    let solid_angle = 2.0 * PI * (1.0 - cos_radius);
+    assert!((sky.pdf(sun) * solid_angle - 1.0).abs() < 1e-2);
+}
+
+#[test]
+fn lambert_scatters_with_a_cosine_lobe() {
+    rng::seed(&[9]);
+    let normal = tilted_normal();
+    let mat = Material::Lambert {
+        albedo: Vec3::one(),
+    };
+    let hit = hit(normal, mat);
+    let r = incoming(Vec3::new(1.0, 1.0, 0.2));
+    assert_directions(
+        "Lambert",
+        normal,
+        || Some(mat.scatter(&r, &hit).1?.dir),
+        |dir| mat.eval(&hit, dir).unwrap().1,
+    );
+}
+
+#[test]
+fn isotropic_scatters_uniformly() {
+    rng::seed(&[10]);
+    let mat = Material::Isotropic {
+        albedo: Vec3::one(),
+    };
+    let hit = hit(Vec3::unit_y(), mat);
+    let r = incoming(Vec3::new(0.0, 1.0, 1.0));
+    assert_directions(
+        "Isotropic",
+        Vec3::unit_x(),
+        || Some(mat.scatter(&r, &hit).1?.dir),
+        |dir| mat.eval(&hit, dir).unwrap().1,
+    );
+}
+
+#[test]
+fn metal_fuzz_perturbs_within_a_ball() {
+    rng::seed(&[11]);
+    // straight down, so that the mirror direction is the normal and nothing falls
+    // below the surface
+    let normal = Vec3::unit_y();
+    let fuzz = 0.6;
+    let mat = Material::Metal {
+        albedo: Vec3::one(),
+        fuzz,
+    };
+    let hit = hit(normal, mat);
+    let r = incoming(Vec3::unit_y());
+
+    // directions of points uniform in the ball of radius fuzz around the mirror
+    // direction, binned within the cone the ball subtends
+    const RINGS: usize = 10;
+    const SECTORS: usize = 8;
+    let cos_max = (1.0 - f64::from(fuzz * fuzz)).sqrt();
+    let (t, b) = orthonormal_basis(normal);
+    let bin = |dir: Vec3| {
+        let dir = dir.normalize();
+        let cos = f64::from(Vec3::dot(dir, normal)).min(1.0);
+        let ring = ((1.0 - cos) / (1.0 - cos_max) * RINGS as f64) as usize;
+        let angle = (Vec3::dot(dir, b).atan2(Vec3::dot(dir, t)) + PI) / (2.0 * PI);
+        let sector = ((angle * SECTORS as f32) as usize).min(SECTORS - 1);
+        ring.min(RINGS - 1) * SECTORS + sector
+    };
+
+    let mut observed = vec![0; RINGS * SECTORS];
+    for _ in 0..SAMPLES / 4 {
+        let scattered = mat.scatter(&r, &hit).1.unwrap();
+        observed[bin(scattered.dir)] += 1;
+    }
+
+    // the ball's volume over a fine grid
+    const STEPS: i32 = 120;
+    let mut expected = vec![0.0; RINGS * SECTORS];
+    for x in -STEPS..STEPS {
+        for y in -STEPS..STEPS {
+            for z in -STEPS..STEPS {
+                let p = (Vec3::new(x as f32, y as f32, z as f32) + Vec3::splat(0.5)) / STEPS as f32;
+                if p.length_squared() <= 1.0 {
+                    expected[bin(normal + fuzz * p)] += 1.0;
+                }
+            }
+        }
+    }
+    let volume: f64 = expected.iter().sum();
+    for e in &mut expected {
+        *e /= volume;
+    }
+    assert_fits("Metal", &observed, &expected);
+
+    // without fuzz it is a mirror
+    let mirror = Material::Metal {
+        albedo: Vec3::one(),
+        fuzz: 0.0,
+    };
+    let r = incoming(Vec3::new(1.0, 2.0, 0.5));
+    let reflected = mirror.scatter(&r, &hit).1.unwrap().dir;
+    assert!((reflected - Vec3::new(-1.0, 2.0, -0.5)).length() < 1e-5);
+}
+
+/// Cosine of the refracted direction for light arriving at `cos_in` from outside glass
+/// of index `ref_idx`.
+fn cos_refracted(cos_in: f32, ref_idx: f32) -> f32 {
+    (1.0 - (1.0 - cos_in * cos_in) / (ref_idx * ref_idx)).sqrt()
+}
+
+#[test]
+fn dielectrics_reflect_with_the_fresnel_probability() {
+    rng::seed(&[12]);
+    let ref_idx = 1.5;
+    let mat = Material::Dielectric {
+        albedo: Vec3::one(),
+        ref_idx,
+    };
+    let normal = Vec3::unit_y();
+    let hit = hit(normal, mat);
+
+    for &cos_in in &[1.0_f32, 0.8, 0.5, 0.2, 0.05] {
+        let sin_in = (1.0 - cos_in * cos_in).sqrt();
+        let r = incoming(Vec3::new(sin_in, cos_in, 0.0));
+        let reflect = schlick(cos_in, ref_idx);
+
+        let mut observed = vec![0; 2];
+        for _ in 0..SAMPLES / 4 {
+            let dir = mat.scatter(&r, &hit).1.unwrap().dir.normalize();
+            if dir.y() > 0.0 {
+                assert!((dir - Vec3::new(-sin_in, cos_in, 0.0)).length() < 1e-4);
+                observed[0] += 1;
+            } else {
+                // Snell's law
+                let cos_out = cos_refracted(cos_in, ref_idx);
+                assert!((-dir.y() - cos_out).abs() < 1e-4);
+                observed[1] += 1;
+            }
+        }
+        let expected = [f64::from(reflect), f64::from(1.0 - reflect)];
+        assert_fits(
+            &format!("Dielectric at cos {}", cos_in),
+            &observed,
+            &expected,
+        );
+    }
+
+    // past the critical angle, light inside the glass can't get out
+    let r = incoming(Vec3::new(0.9, -0.3, 0.0));
+    for _ in 0..100 {
+        assert!(mat.scatter(&r, &hit).1.unwrap().dir.y() < 0.0);
+    }
+}
+
+#[test]
+fn materials_do_not_create_energy() {
+    rng::seed(&[13]);
+    let normal = tilted_normal();
+    // grazing, so that fuzz has somewhere to lose light
+    let (tangent, _) = orthonormal_basis(normal);
+    let r = incoming(0.2 * normal + tangent);
+    let materials = [
+        (
+            Material::Lambert {
+                albedo: Vec3::one(),
+            },
+            true,
+        ),
+        (
+            Material::Isotropic {
+                albedo: Vec3::one(),
+            },
+            true,
+        ),
+        (
+            Material::Dielectric {
+                albedo: Vec3::one(),
+                ref_idx: 1.5,
+            },
+            true,
+        ),
+        (
+            Material::Metal {
+                albedo: Vec3::one(),
+                fuzz: 0.0,
+            },
+            true,
+        ),
+        // fuzz that points below the surface is absorbed
+        (
+            Material::Metal {
+                albedo: Vec3::one(),
+                fuzz: 0.8,
+            },
+            false,
+        ),
+    ];
+
+    for &(mat, lossless) in &materials {
+        let hit = hit(normal, mat);
+        // the weights scatter returns are the reflected energy, estimated over its samples
+        let mut reflected = 0.0;
+        for _ in 0..SAMPLES / 4 {
+            if let (attenuation, Some(_)) = mat.scatter(&r, &hit) {
+                reflected += f64::from(attenuation.max_element());
+            }
+        }
+        let albedo = reflected / (SAMPLES / 4) as f64;
+        assert!(
+            albedo <= 1.0 + 1e-9,
+            "material id {} reflects {}",
+            mat.id(),
+            albedo
+        );
+        if lossless {
+            assert!((albedo - 1.0).abs() < 1e-9);
+        } else {
+            assert!(albedo < 0.99);
+        }
+
+        // what eval says about the same material, integrated over the sphere
+        if mat.eval(&hit, normal).is_some() {
+            let (mut f, mut pdf) = (0.0, 0.0);
+            for _ in 0..SAMPLES {
+                let (value, p) = mat.eval(&hit, random_unit_vector()).unwrap();
+                f += f64::from(value.max_element()) * 4.0 * f64::from(PI);
+                pdf += f64::from(p) * 4.0 * f64::from(PI);
+            }
+            let (f, pdf) = (f / SAMPLES as f64, pdf / SAMPLES as f64);
+            assert!((f - 1.0).abs() < 0.01, "eval integrates to {}", f);
+            assert!((pdf - 1.0).abs() < 0.01, "pdf integrates to {}", pdf);
+        }
+    }
+}
+
+#[test]
+fn materials_are_reciprocal() {
+    rng::seed(&[14]);
+    let normal = tilted_normal();
+
+    // diffuse BSDFs, eval divided by the cosine, don't depend on the incoming direction
+    for &mat in &[
+        Material::Lambert {
+            albedo: Vec3::new(0.8, 0.5, 0.2),
+        },
+        Material::Isotropic {
+            albedo: Vec3::new(0.8, 0.5, 0.2),
+        },
+    ] {
+        let hit = hit(normal, mat);
+        let bsdf = |dir: Vec3| {
+            let (value, _) = mat.eval(&hit, dir).unwrap();
+            match mat {
+                Material::Lambert { .. } => value / Vec3::dot(normal, dir),
+                _ => value,
+            }
+        };
+        for _ in 0..1000 {
+            let (a, b) = (random_unit_vector(), random_unit_vector());
+            let (a, b) = (
+                if Vec3::dot(a, normal) < 0.0 { -a } else { a },
+                if Vec3::dot(b, normal) < 0.0 { -b } else { b },
+            );
+            let (ab, ba) = (bsdf(b), bsdf(a));
+            assert!((ab - ba).length() < 1e-3 * ab.length().max(1.0));
+        }
+    }
+
+    // a mirror sends light back the way it came when the path is reversed
+    let mirror = Material::Metal {
+        albedo: Vec3::one(),
+        fuzz: 0.0,
+    };
+    let hit_mirror = hit(normal, mirror);
+    for _ in 0..100 {
+        let from = random_unit_vector() + normal * 1.5;
+        let out = mirror.scatter(&incoming(from), &hit_mirror).1.unwrap().dir;
+        let back = mirror.scatter(&incoming(out), &hit_mirror).1.unwrap().dir;
+        assert!((back.normalize() - from.normalize()).length() < 1e-4);
+    }
+
+    // glass refracts reversed paths back and reflects as much light each way
+    let ref_idx = 1.5;
+    let glass = Material::Dielectric {
+        albedo: Vec3::one(),
+        ref_idx,
+    };
+    let hit_glass = hit(Vec3::unit_y(), glass);
+    for &cos_in in &[0.9_f32, 0.6, 0.3] {
+        let sin_in = (1.0 - cos_in * cos_in).sqrt();
+        let cos_out = cos_refracted(cos_in, ref_idx);
+        let sin_out = (1.0 - cos_out * cos_out).sqrt();
+        let into = incoming(Vec3::new(sin_in, cos_in, 0.0));
+        let out_of = incoming(Vec3::new(-sin_out, -cos_out, 0.0));
+
+        let fractions = |r: &Ray| {
+            let (mut refracted, mut dir) = (0, Vec3::zero());
+            for _ in 0..SAMPLES / 4 {
+                let scattered = glass.scatter(r, &hit_glass).1.unwrap().dir.normalize();
+                if Vec3::dot(scattered, r.dir) > 0.0 && scattered.y() * r.dir.y() > 0.0 {
+                    refracted += 1;
+                    dir = scattered;
+                }
+            }
+            (refracted, dir)
+        };
+        let ((into_count, into_dir), (out_count, out_dir)) = (fractions(&into), fractions(&out_of));
+        assert!((into_dir + out_of.dir.normalize()).length() < 1e-4 || into_count == 0);
+        assert!((out_dir + into.dir.normalize()).length() < 1e-4 || out_count == 0);
+
+        // both are binomial with the same probability, compared as a 2x2 table
+        let n = (SAMPLES / 4) as f64;
+        let p = (into_count + out_count) as f64 / (2.0 * n);
+        let difference = (into_count as f64 - out_count as f64) / n;
+        let sigma = (2.0 * p * (1.0 - p) / n).sqrt();
+        assert!(
+            difference.abs() <= Z * sigma,
+            "at cos {}: {} refracted going in, {} coming out",
+            cos_in,
+            into_count,
+            out_count
+        );
+    }
+}
+
+/// A closed world lit by a white environment, where lossless materials must come out
+/// exactly white.
+fn furnace(world: World) -> Scene {
+    let mut scene = Scene::new(world);
+    scene.environment = Box::new(Constant { color: Vec3::one() });
+    scene
+}
+
+fn assert_white(name: &str, color: &[Vec3]) {
+    let mean = color.iter().fold(Vec3::zero(), |sum, &c| sum + c) / color.len() as f32;
+    assert!(
+        (mean - Vec3::one()).length() < 1e-3,
+        "{} furnace comes out {:?}",
+        name,
+        mean
+    );
+}
+
+#[test]
+fn white_furnace() {
+    let settings = RenderSettings {
+        dimensions: (16, 16),
+        samples: 16,
+        depth: 256,
+        roulette: None,
+        seed: Some(15),
+        aovs: Vec::new(),
+    };
+    let camera = Camera::new(
+        Vec3::new(0.0, 0.0, 3.0),
+        Vec3::zero(),
+        Vec3::unit_y(),
+        50.0,
+        1.0,
+        0.0,
+        3.0,
+    );
+    let white = Vec3::one();
+    let materials = [
+        ("Lambert", Material::Lambert { albedo: white }),
+        (
+            "Dielectric",
+            Material::Dielectric {
+                albedo: white,
+                ref_idx: 1.5,
+            },
+        ),
+        (
+            "Metal",
+            Material::Metal {
+                albedo: white,
+                fuzz: 0.0,
+            },
+        ),
+    ];
+
+    for &(name, mat) in &materials {
+        // spheres close enough that light bounces between them
+        let world: World = vec![
+            Box::new(Sphere::new(Vec3::new(-0.5, 0.0, 0.0), 0.5, mat)),
+            Box::new(Sphere::new(Vec3::new(0.5, 0.0, 0.0), 0.5, mat)),
+            Box::new(Sphere::new(Vec3::new(0.0, -100.5, 0.0), 100.0, mat)),
+        ];
+        let scene = furnace(world);
+        assert_white(name, &trace::render(&camera, &scene, &settings).color);
+        assert_white(
+            name,
+            &Wavefront::default()
+                .render(&camera, &scene, &settings)
+                .color,
+        );
+    }
+
+    let cloud: World = vec![Box::new(Volume::new(
+        Aabb::new(Vec3::splat(-1.0), Vec3::splat(1.0)),
+        VoxelGrid::new([2, 2, 2], vec![1.0; 8]),
+        4.0,
+        white,
+    ))];
+    let scene = furnace(cloud);
+    assert_white(
+        "Isotropic",
+        &trace::render(&camera, &scene, &settings).color,
+    );
+    assert_white(
+        "Isotropic",
+        &Wavefront::default()
+            .render(&camera, &scene, &settings)
+            .color,
+    );
+
+    // fuzzed metal absorbs what it would scatter below the surface, but never adds
+    let fuzzy = Material::Metal {
+        albedo: white,
+        fuzz: 0.8,
+    };
+    let scene = furnace(vec![Box::new(Sphere::new(Vec3::zero(), 1.0, fuzzy))]);
+    for c in trace::render(&camera, &scene, &settings).color {
+        assert!(c.max_element() <= 1.0 + 1e-4);
+    }
+}
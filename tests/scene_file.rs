@@ -0,0 +1,117 @@
+//! Saving a scene file has to keep `animate` lines pointing at the objects they animate,
+//! and loading one rejects keys an animation can't play.
+
+use glam::Vec3;
+use std::fs;
+use std::path::PathBuf;
+use tracer::animation::{Interpolation, Key, Parameter, Track};
+use tracer::scene_file;
+use tracer::sphere::Sphere;
+use tracer::volume::Volume;
+
+const SCENE: &str = "\
+camera origin=0,0.5,2 lookat=0,0,-1 fov=60
+# ground first, then a cloud between two spheres
+sphere center=0,-100.5,-1 radius=100 material=lambert albedo=0.5
+volume min=-1,0,-2 max=1,1,-1 grid=noise resolution=4,4,4 density=10
+sphere center=0.5,0,-1 radius=0.25 material=metal albedo=0.8 fuzz=0.1
+light type=point position=0,1,0 intensity=1,1,1
+animate parameter=center object=2 time=0 value=0.5,0,-1
+animate parameter=center object=2 time=1 value=0.5,1,-1 interpolation=bezier
+animate parameter=fov time=1 value=45
+";
+
+/// A copy of `SCENE` of its own in the temporary directory.
+fn scene_path(name: &str) -> String {
+    let path: PathBuf = std::env::temp_dir().join(format!("tracer-{}.scene", name));
+    fs::write(&path, SCENE).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+fn is_volume(scene: &tracer::scene::Scene, index: usize) -> bool {
+    scene.world[index].as_ref().as_any().is::<Volume>()
+}
+
+#[test]
+fn saving_keeps_objects_in_world_order() {
+    let path = scene_path("round-trip");
+    let (scene, _) = scene_file::load(&path).unwrap();
+    scene_file::save(&path, &scene).unwrap();
+    let (saved, _) = scene_file::load(&path).unwrap();
+
+    assert_eq!(saved.world.len(), 3);
+    assert!(is_volume(&saved, 1));
+    let sphere = saved.sphere(2).unwrap();
+    assert_eq!(sphere.center, Vec3::new(0.5, 0.0, -1.0));
+    assert_eq!(saved.animation.tracks.len(), 2);
+    assert_eq!(saved.animation.tracks[0].parameter, Parameter::Center(2));
+    assert_eq!(
+        saved.animation.tracks[0].keys,
+        scene.animation.tracks[0].keys
+    );
+    assert_eq!(
+        saved.animation.tracks[1].keys,
+        scene.animation.tracks[1].keys
+    );
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn removing_an_object_renumbers_its_successors() {
+    let path = scene_path("remove");
+    let (mut scene, _) = scene_file::load(&path).unwrap();
+
+    // a copy at the end of the world, then the ground out of it
+    let copy: Sphere = scene.sphere(2).unwrap().clone();
+    scene.world.push(Box::new(copy));
+    scene.remove(0);
+    assert_eq!(scene.animation.tracks[0].parameter, Parameter::Center(1));
+
+    scene_file::save(&path, &scene).unwrap();
+    let (saved, _) = scene_file::load(&path).unwrap();
+    assert_eq!(saved.world.len(), 3);
+    assert!(is_volume(&saved, 0));
+    assert!(saved.sphere(1).is_some() && saved.sphere(2).is_some());
+    assert_eq!(saved.animation.tracks[0].parameter, Parameter::Center(1));
+
+    // removing the animated sphere takes its track with it
+    let mut scene = saved;
+    scene.remove(1);
+    assert!(scene
+        .animation
+        .tracks
+        .iter()
+        .all(|track| track.parameter.object().is_none()));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn keys_that_are_not_finite_are_errors() {
+    let path = std::env::temp_dir().join("tracer-not-finite.scene");
+    for line in &[
+        "animate parameter=fov time=NaN value=45",
+        "animate parameter=fov time=inf value=45",
+        "animate parameter=center object=2 time=1 value=0,NaN,-1",
+    ] {
+        fs::write(&path, format!("{}{}\n", SCENE, line)).unwrap();
+        let error = scene_file::load(&path.to_string_lossy()).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{}", line);
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn tracks_with_nan_keys_do_not_panic() {
+    let key = |time| Key {
+        time,
+        value: Vec3::splat(time),
+        interpolation: Interpolation::Linear,
+    };
+    let track = Track {
+        parameter: Parameter::Fov,
+        keys: vec![key(f32::NAN), key(5.0)],
+    };
+    for &time in &[-1.0, 1.0, 5.0, 6.0, f32::NAN] {
+        assert!(track.value_at(time).is_some());
+    }
+}
@@ -0,0 +1,50 @@
+//! The temporal accumulator only averages frames seen the same way.
+
+use glam::{Vec2, Vec3};
+use tracer::camera::{Aperture, Camera};
+use tracer::hit::Hittable;
+use tracer::temporal::TemporalAccumulator;
+
+const DIMENSIONS: (usize, usize) = (4, 4);
+
+/// Samples of the history after a dozen frames from `camera`, then one from `changed`.
+fn samples_after_change(camera: &Camera, changed: &Camera) -> u32 {
+    let world: Vec<Box<dyn Hittable>> = Vec::new();
+    let color = vec![Vec3::one(); DIMENSIONS.0 * DIMENSIONS.1];
+    let mut accumulator = TemporalAccumulator::new(0.9);
+    for _ in 0..12 {
+        accumulator.accumulate(camera, &world, DIMENSIONS, &color);
+    }
+    accumulator.accumulate(changed, &world, DIMENSIONS, &color);
+    accumulator.samples()
+}
+
+#[test]
+fn changing_the_projection_ends_the_average() {
+    let camera = Camera::new(
+        Vec3::zero(),
+        -Vec3::unit_z(),
+        Vec3::unit_y(),
+        60.0,
+        1.0,
+        0.1,
+        1.0,
+    );
+    assert_eq!(samples_after_change(&camera, &camera.clone()), 13);
+
+    let mut zoomed = camera.clone();
+    zoomed.set_fov(30.0);
+    let mut bokeh = camera.clone();
+    bokeh.aperture = Aperture::Polygon {
+        blades: 6,
+        rotation: 0.0,
+    };
+    let mut squeezed = camera.clone();
+    squeezed.anamorphic_squeeze = 2.0;
+    let mut tilted = camera.clone();
+    tilted.focus_tilt = Vec2::new(5.0, 0.0);
+    for changed in &[zoomed, bokeh, squeezed, tilted] {
+        // back to the exponential blend of a moving camera
+        assert_eq!(samples_after_change(&camera, changed), 10);
+    }
+}
@@ -0,0 +1,149 @@
+//! The realtime pipeline, driven without a window the way `realtime --headless` does.
+
+use glam::Vec3;
+use tracer::camera::Camera;
+use tracer::camera_path::{CameraPath, Keyframe};
+use tracer::environment::Constant;
+use tracer::material::Material;
+use tracer::scene::Scene;
+use tracer::sphere::Sphere;
+use tracer::trace::{self, RenderSettings};
+use tracer::viewer::{CameraController, Viewer};
+
+const DIMENSIONS: (usize, usize) = (32, 24);
+
+/// A red sphere ahead and nothing but grey behind.
+fn scene() -> Scene {
+    let mut scene = Scene::new(vec![Box::new(Sphere::new(
+        Vec3::new(0.0, 0.0, -3.0),
+        1.0,
+        Material::Lambert {
+            albedo: Vec3::new(0.9, 0.1, 0.1),
+        },
+    ))]);
+    scene.environment = Box::new(Constant {
+        color: Vec3::splat(0.5),
+    });
+    scene
+}
+
+fn camera() -> Camera {
+    Camera::new(
+        Vec3::zero(),
+        -Vec3::unit_z(),
+        Vec3::unit_y(),
+        60.0,
+        1.0,
+        0.0,
+        1.0,
+    )
+}
+
+/// RGB of the pixel at the center of a frame.
+fn center(pixels: &[u8]) -> [u8; 3] {
+    let index = 4 * (DIMENSIONS.1 / 2 * DIMENSIONS.0 + DIMENSIONS.0 / 2);
+    [pixels[index], pixels[index + 1], pixels[index + 2]]
+}
+
+#[test]
+fn replays_camera_paths() {
+    // turns around from the sphere over a second
+    let path = CameraPath {
+        keyframes: vec![
+            Keyframe {
+                time: 0.0,
+                origin: Vec3::zero(),
+                angles: Vec3::zero(),
+            },
+            Keyframe {
+                time: 1.0,
+                origin: Vec3::zero(),
+                angles: Vec3::new(180.0, 0.0, 0.0),
+            },
+        ],
+    };
+
+    let mut frames = Vec::new();
+    Viewer::default()
+        .replay(
+            &scene(),
+            &camera(),
+            &path,
+            DIMENSIONS,
+            4.0,
+            |index, pixels| {
+                frames.push((index, pixels.to_vec()));
+                Ok(())
+            },
+        )
+        .unwrap();
+
+    assert_eq!(frames.len(), 5);
+    for (expected, (index, pixels)) in frames.iter().enumerate() {
+        assert_eq!(*index, expected);
+        assert_eq!(pixels.len(), DIMENSIONS.0 * DIMENSIONS.1 * 4);
+        assert!(pixels.chunks(4).all(|pixel| pixel[3] == 255));
+    }
+
+    let [r, g, b] = center(&frames[0].1);
+    assert!(
+        r > 2 * g && r > 2 * b,
+        "first frame center is {:?}",
+        [r, g, b]
+    );
+    let [r, g, b] = center(&frames[4].1);
+    assert!(
+        r == g && g == b && r > 100,
+        "last frame center is {:?}",
+        [r, g, b]
+    );
+}
+
+#[test]
+fn controllers_take_the_poses_of_paths() {
+    let keyframe = Keyframe {
+        time: 0.0,
+        origin: Vec3::new(1.0, 2.0, 3.0),
+        angles: Vec3::new(-120.0, 30.0, 20.0),
+    };
+    let mut controller = CameraController::new(&camera());
+    controller.set_pose(keyframe.origin, keyframe.rotation());
+
+    assert_eq!(controller.origin, keyframe.origin);
+    assert!((controller.angles - keyframe.angles).length() < 1e-3);
+}
+
+#[test]
+fn denoised_frames_converge_while_the_camera_stays() {
+    let (scene, camera) = (scene(), camera());
+    let reference = trace::render(
+        &camera,
+        &scene,
+        &RenderSettings {
+            dimensions: DIMENSIONS,
+            samples: 256,
+            depth: 50,
+            roulette: None,
+            seed: Some(1),
+            aovs: Vec::new(),
+        },
+    );
+    let error = |color: &[Vec3]| {
+        let sum: f32 = color
+            .iter()
+            .zip(&reference.color)
+            .map(|(a, b)| (*a - *b).length())
+            .sum();
+        sum / color.len() as f32
+    };
+
+    // the denoiser blurs edges, so only history of raw radiance gets close to the mean
+    let mut viewer = Viewer::default();
+    let first = error(viewer.frame(&camera, &scene, DIMENSIONS).0);
+    for _ in 0..62 {
+        viewer.frame(&camera, &scene, DIMENSIONS);
+    }
+    let last = error(viewer.frame(&camera, &scene, DIMENSIONS).0);
+    assert_eq!(viewer.accumulator.samples(), 64);
+    assert!(last < 0.5 * first, "error went from {} to {}", first, last);
+}
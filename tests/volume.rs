@@ -0,0 +1,115 @@
+//! Raw voxel grids read back as written, broken headers and densities are errors, and
+//! tracking through a volume attenuates rays as Beer-Lambert says it should.
+
+use glam::Vec3;
+use std::fs;
+use std::io::ErrorKind;
+use tracer::aabb::Aabb;
+use tracer::hit::Hittable;
+use tracer::ray::Ray;
+use tracer::rng;
+use tracer::volume::{Volume, VoxelGrid};
+
+fn path(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("tracer-{}.raw", name))
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[test]
+fn reads_back_what_it_writes() {
+    let path = path("voxels");
+    let grid = VoxelGrid::new([2, 3, 1], vec![0.0, 0.5, 1.0, 1.5, 2.0, 2.5]);
+    grid.write_raw(&path).unwrap();
+    let loaded = VoxelGrid::load_raw(&path).unwrap();
+    assert_eq!(loaded.resolution, grid.resolution);
+    assert_eq!(loaded.data, grid.data);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn broken_headers_are_errors() {
+    let path = path("broken-voxels");
+    let header = |resolution: [u32; 3]| -> Vec<u8> {
+        let mut bytes: Vec<u8> = resolution
+            .iter()
+            .flat_map(|n| n.to_le_bytes().to_vec())
+            .collect();
+        bytes.extend_from_slice(&1f32.to_le_bytes());
+        bytes
+    };
+    let resolutions = [
+        [1, 1, 2],
+        [0, 0, 0],
+        // more voxels than a usize counts
+        [0x4000_0000, 0x4000_0000, 0x4000_0000],
+        [u32::MAX, u32::MAX, u32::MAX],
+    ];
+    for &resolution in &resolutions {
+        fs::write(&path, header(resolution)).unwrap();
+        let error = VoxelGrid::load_raw(&path)
+            .err()
+            .expect("load a broken grid");
+        assert_eq!(error.kind(), ErrorKind::InvalidData, "{:?}", resolution);
+    }
+    fs::write(&path, [0; 8]).unwrap();
+    assert!(VoxelGrid::load_raw(&path).is_err());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn negative_and_nan_densities_are_errors() {
+    let path = path("bad-densities");
+    for &density in &[-1.0, f32::NAN, f32::INFINITY] {
+        VoxelGrid::new([2, 1, 1], vec![0.5, density])
+            .write_raw(&path)
+            .unwrap();
+        let error = VoxelGrid::load_raw(&path)
+            .err()
+            .expect("load a grid with a broken density");
+        assert_eq!(error.kind(), ErrorKind::InvalidData, "{}", density);
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn constant_density_transmittance_follows_beer_lambert() {
+    rng::seed(&[26]);
+    const RAYS: usize = 100_000;
+    let (density, distance) = (0.5, 2.0);
+    let volume = Volume::new(
+        Aabb::new(-Vec3::one(), Vec3::one()),
+        VoxelGrid::new([2, 2, 2], vec![1.0; 8]),
+        density,
+        Vec3::one(),
+    );
+    // a direction that isn't unit length checks that t is scaled to distances
+    let ray = Ray::new(Vec3::new(-3.0, 0.1, -0.2), Vec3::new(2.0, 0.0, 0.0));
+    let range = [0.0, f32::MAX];
+    let expected = f64::from((-density * distance).exp());
+
+    let ratio_tracked: f64 = (0..RAYS)
+        .map(|_| f64::from(volume.transmittance(&ray, range)))
+        .sum::<f64>()
+        / RAYS as f64;
+    let delta_tracked = (0..RAYS)
+        .filter(|_| volume.hit(&ray, range).is_none())
+        .count() as f64
+        / RAYS as f64;
+
+    // four standard deviations of a pass or fail average
+    let tolerance = 4.0 * (expected * (1.0 - expected) / RAYS as f64).sqrt();
+    assert!(
+        (ratio_tracked - expected).abs() < tolerance,
+        "ratio tracking {} against {}",
+        ratio_tracked,
+        expected
+    );
+    assert!(
+        (delta_tracked - expected).abs() < tolerance,
+        "delta tracking {} against {}",
+        delta_tracked,
+        expected
+    );
+}
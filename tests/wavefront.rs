@@ -0,0 +1,139 @@
+//! The wavefront integrator takes its random decisions in a different order than the
+//! recursive one, so the two are compared once the noise averages out.
+
+mod common;
+
+use glam::Vec3;
+use tracer::camera::Camera;
+use tracer::frame::Aov;
+use tracer::material::Material;
+use tracer::scene::{self, Scene};
+use tracer::sphere::Sphere;
+use tracer::trace::{self, RenderSettings};
+use tracer::wavefront::Wavefront;
+
+fn camera(aspect: f32) -> Camera {
+    common::camera(
+        Vec3::new(0.0, 0.5, 2.0),
+        Vec3::new(0.0, 0.0, -1.0),
+        60.0,
+        aspect,
+    )
+}
+
+fn settings(dimensions: (usize, usize), samples: i32) -> RenderSettings {
+    common::settings(dimensions, samples, 8, None)
+}
+
+fn assert_matches_recursive(scene: &Scene, wavefront: &Wavefront) {
+    let dimensions = (48, 24);
+    let settings = settings(dimensions, 256);
+    let camera = camera(2.0);
+
+    let recursive = trace::render(&camera, scene, &settings);
+    let iterative = wavefront.render(&camera, scene, &settings);
+    assert_eq!(iterative.rays.camera, recursive.rays.camera);
+
+    common::assert_same_image(&iterative.color, &recursive.color, dimensions);
+}
+
+#[test]
+fn matches_recursive_under_the_sky() {
+    let scene = Scene::new(scene::default_world());
+    assert_matches_recursive(&scene, &Wavefront::default());
+    assert_matches_recursive(&scene, &Wavefront { sort: false });
+}
+
+#[test]
+fn matches_recursive_with_lights() {
+    assert_matches_recursive(&scene::lights_scene(), &Wavefront::default());
+}
+
+#[test]
+fn matches_recursive_through_a_volume() {
+    // not only spheres, so the world is traced as it is rather than through a BVH
+    let scene = Scene::new(scene::cloud_world());
+    assert_matches_recursive(&scene, &Wavefront::default());
+}
+
+#[test]
+fn light_aovs_add_up_to_the_color() {
+    let dimensions = (16, 16);
+    let mut settings = settings(dimensions, 4);
+    settings.aovs = vec![Aov::Direct, Aov::Indirect, Aov::Depth];
+    let frame = Wavefront::default().render(&camera(1.0), &scene::lights_scene(), &settings);
+
+    let direct = frame.aov(Aov::Direct).unwrap();
+    let indirect = frame.aov(Aov::Indirect).unwrap();
+    for ((color, direct), indirect) in frame.color.iter().zip(direct).zip(indirect) {
+        assert!((*color - (*direct + *indirect)).length() < 1e-4);
+    }
+    assert!(frame.aov(Aov::Depth).unwrap().iter().all(|d| d.x() > 0.0));
+}
+
+#[test]
+fn seeded_renders_repeat() {
+    let mut settings = settings((16, 16), 4);
+    settings.seed = Some(3);
+    let scene = Scene::new(scene::cloud_world());
+    let render = || Wavefront::default().render(&camera(1.0), &scene, &settings);
+    assert_eq!(render().color, render().color);
+}
+
+#[test]
+fn node_visits_add_up_over_threads() {
+    let mut settings = settings((16, 16), 4);
+    settings.seed = Some(5);
+    let camera = camera(1.0);
+
+    // seeded renders open the same nodes however the pixels are spread over threads
+    let scene = scene::lights_scene();
+    let visits = || {
+        (
+            trace::render(&camera, &scene, &settings).rays.node_visits,
+            Wavefront::default()
+                .render(&camera, &scene, &settings)
+                .rays
+                .node_visits,
+        )
+    };
+    let (recursive, wavefront) = visits();
+    assert!(recursive > 0 && wavefront > 0);
+    assert_eq!(visits(), (recursive, wavefront));
+
+    let cloud = Scene::new(scene::cloud_world());
+    assert_eq!(
+        trace::render(&camera, &cloud, &settings).rays.node_visits,
+        0
+    );
+}
+
+#[test]
+fn ids_come_from_the_first_sample_that_hits() {
+    // a small sphere leaves many pixels on its edge that some samples miss
+    let scene = Scene::new(vec![Box::new(Sphere::new(
+        Vec3::new(0.0, 0.0, -1.0),
+        0.3,
+        Material::Lambert {
+            albedo: Vec3::splat(0.5),
+        },
+    ))]);
+    let mut settings = settings((16, 16), 8);
+    settings.aovs = vec![Aov::Depth, Aov::ObjectId, Aov::MaterialId];
+
+    let frames = [
+        trace::render(&camera(1.0), &scene, &settings),
+        Wavefront::default().render(&camera(1.0), &scene, &settings),
+    ];
+    for frame in &frames {
+        let depth = frame.aov(Aov::Depth).unwrap();
+        let object = frame.aov(Aov::ObjectId).unwrap();
+        let material = frame.aov(Aov::MaterialId).unwrap();
+        for ((depth, object), material) in depth.iter().zip(object).zip(material) {
+            // a pixel has ids exactly when one of its samples hit something
+            let hit = depth.x().is_finite();
+            assert_eq!(object.x() == 1.0, hit, "{:?}", object);
+            assert_eq!(material.x() > 0.0, hit, "{:?}", material);
+        }
+    }
+}